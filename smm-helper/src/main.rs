@@ -1,10 +1,41 @@
 use anyhow::{anyhow, Result};
 use clap::{self, FromArgMatches, Subcommand};
 use std::{
-    fs::{File, self},
-    io::Write,
-    process::Command,
+    collections::BTreeMap,
+    fs::{File, self, DirBuilder, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::{
+        fs::{fchown, DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+        io::AsRawFd,
+        process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
 };
+use users::os::unix::UserExt;
+
+/// Directory the helper keeps its state in, readable by everyone
+const STATE_DIR: &str = "/var/lib/snowflakeos-module-manager";
+
+/// Directory secrets are stored in, readable only by root
+const SECRETS_DIR: &str = "/var/lib/snowflakeos-module-manager/secrets";
+
+/// nix-data configuration of the caller, relative to their home directory.
+/// It names the system configuration and flake the module manager edits.
+const NIX_DATA_CONFIG: &str = ".config/nix-data/config.json";
+
+/// Held while the helper changes the system, so a second invocation can not recover or overwrite
+/// the files of a rebuild that is still running
+const LOCK_FILE: &str = "/run/smm-helper.lock";
+
+
+/// Profile holding the system generations
+const SYSTEM_PROFILE: &str = "/nix/var/nix/profiles/system";
+
+/// Directory the `modules.nix` and `flake.nix` of each generation are saved in, as `<number>.nix`
+/// and `<number>.flake.nix`
+const GENERATIONS_DIR: &str = "/var/lib/snowflakeos-module-manager/generations";
 
 #[derive(Subcommand, Debug)]
 enum SubCommands {
@@ -16,96 +47,921 @@ enum SubCommands {
         generations: Option<u32>,
     },
     WriteRebuild {
-        /// Content to write to file
-        #[arg(short, long)]
-        content: String,
-        /// Write config to file in path output
-        #[arg(short, long)]
-        path: String,
+        /// Content to write to file, may be repeated together with `--path`
+        #[arg(short, long, required = true)]
+        content: Vec<String>,
+        /// Write config to file in path output, paired with `--content` in order
+        #[arg(short, long, required = true)]
+        path: Vec<String>,
+        /// Name of a secret to store, may be repeated together with `--secret-file`
+        #[arg(long)]
+        secret: Vec<String>,
+        /// File to read the secret from, paired with `--secret` in order
+        #[arg(long)]
+        secret_file: Vec<String>,
+        /// Restore the original files after rebuilding, so nothing but the build is kept
+        #[arg(long)]
+        restore: bool,
         /// Run `nixos-rebuild` with the given arguments
         arguments: Vec<String>,
         /// How many generations to keep
         #[arg(short, long)]
         generations: Option<u32>,
     },
+    Rollback {
+        /// Generation to switch to, defaults to the one before the current generation
+        #[arg(short, long)]
+        generation: Option<u32>,
+    },
+    /// List system generations, one per line as number, date, NixOS version and `current` separated by tabs
+    ListGenerations,
 }
 
 fn main() {
-    let cli = SubCommands::augment_subcommands(clap::Command::new(
-        "Helper binary for SnowflakeOS Module Manager",
-    ));
+    let cli = SubCommands::augment_subcommands(
+        clap::Command::new("Helper binary for SnowflakeOS Module Manager").arg(
+            clap::Arg::new("progress")
+                .long("progress")
+                .global(true)
+                .help("Existing file owned by the caller to append progress events to"),
+        ),
+    );
     let matches = cli.get_matches();
     let derived_subcommands = SubCommands::from_arg_matches(&matches)
         .map_err(|err| err.exit())
         .unwrap();
 
+    // Listing generations only reads the world-readable system profile
+    if let SubCommands::ListGenerations = derived_subcommands {
+        if let Err(err) = list_generations() {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if users::get_effective_uid() != 0 {
         eprintln!("snn-helper must be run as root");
         std::process::exit(1);
     }
 
-    match derived_subcommands {
-        SubCommands::Rebuild { arguments, generations } => match rebuild(arguments, generations) {
-            Ok(_) => (),
-            Err(err) => {
-                eprintln!("{}", err);
-                std::process::exit(1);
+    let mut progress = match matches.get_one::<String>("progress") {
+        Some(path) => Progress::open(Path::new(path)).unwrap_or_else(|err| {
+            eprintln!("Not reporting progress: {}", err);
+            Progress::default()
+        }),
+        None => Progress::default(),
+    };
+
+    // Held until the helper exits
+    let _lock = match lock() {
+        Ok(lock) => lock,
+        Err(err) => {
+            eprintln!("{}", err);
+            progress.emit(&["error", &err.to_string()]);
+            std::process::exit(1);
+        }
+    };
+
+    let config = Config::load();
+    recover(config.as_ref().ok());
+    let result = (|| -> Result<()> {
+        match derived_subcommands {
+            SubCommands::Rebuild { arguments, generations } => {
+                rebuild(arguments, generations, &mut progress)
             }
-        },
-        SubCommands::WriteRebuild {
-            content,
-            path,
-            arguments,
-            generations
-        } => {
-            match write_file(&content, &path, arguments, generations) {
-                Ok(_) => (),
-                Err(err) => {
-                    eprintln!("{}", err);
-                    std::process::exit(1);
+            SubCommands::WriteRebuild {
+                content,
+                path,
+                secret,
+                secret_file,
+                restore,
+                arguments,
+                generations
+            } => {
+                if content.len() != path.len() {
+                    return Err(anyhow!("Each --content must be paired with a --path"));
                 }
-            };
+                if secret.len() != secret_file.len() {
+                    return Err(anyhow!("Each --secret must be paired with a --secret-file"));
+                }
+                let config = config
+                    .as_ref()
+                    .map_err(|err| anyhow!("Failed to read the nix-data configuration: {}", err))?;
+                let files = path.into_iter().zip(content).collect::<Vec<_>>();
+                for (path, content) in &files {
+                    check_target(path, content, config)?;
+                }
+                let secrets = read_secrets(secret, secret_file)?;
+                write_file(&files, &secrets, config, restore, arguments, generations, &mut progress)
+            }
+            SubCommands::Rollback { generation } => rollback(generation, &mut progress),
+            SubCommands::ListGenerations => unreachable!(),
+        }
+    })();
+
+    match result {
+        Ok(_) => progress.emit(&["done"]),
+        Err(err) => {
+            eprintln!("{}", err);
+            progress.emit(&["error", &err.to_string()]);
+            std::process::exit(1);
+        }
+    }
+}
+
+/**
+ * Progress events for the rebuild dialog, appended to the file given with `--progress`.
+ * Each event is a line of tab separated fields: a phase such as `evaluating`, optionally followed by
+ * the number of finished and total steps, `error` followed by a message, or `done`.
+ */
+#[derive(Default)]
+struct Progress {
+    file: Option<File>,
+    /// Finished and total derivations to build
+    building: (u32, u32),
+    /// Finished and total store paths to download
+    downloading: (u32, u32),
+}
+
+impl Progress {
+    /// Open the progress file, which has to be a file of the caller.
+    fn open(path: &Path) -> Result<Self> {
+        let file = open_caller_file(path, OpenOptions::new().append(true))?;
+        Ok(Progress {
+            file: Some(file),
+            ..Default::default()
+        })
+    }
+
+    fn emit(&mut self, fields: &[&str]) {
+        if let Some(file) = &mut self.file {
+            let line = fields
+                .iter()
+                .map(|x| x.replace(['\t', '\n'], " "))
+                .collect::<Vec<_>>()
+                .join("\t");
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn phase(&mut self, phase: &str) {
+        self.emit(&[phase]);
+    }
+
+    fn steps(&mut self, phase: &str, (done, total): (u32, u32)) {
+        self.emit(&[phase, &done.min(total).to_string(), &total.to_string()]);
+    }
+
+    /// Follow a line of `nixos-rebuild` output, emitting events when a phase starts or advances.
+    fn track(&mut self, line: &str) {
+        let line = line.trim();
+        if let Some(total) = announced(line, "derivation", "will be built") {
+            self.building = (0, total);
+            self.steps("building", self.building);
+        } else if let Some(total) = announced(line, "path", "will be fetched") {
+            self.downloading = (0, total);
+            self.steps("downloading", self.downloading);
+        } else if line.starts_with("building '") {
+            self.building.0 += 1;
+            self.steps("building", self.building);
+        } else if line.starts_with("copying path '") {
+            self.downloading.0 += 1;
+            self.steps("downloading", self.downloading);
+        } else if line.starts_with("activating the configuration") || line.starts_with("updating GRUB") {
+            self.phase("activating");
         }
     }
 }
 
-fn write_file(content: &str, path: &str, args: Vec<String>, generations: Option<u32>) -> Result<()> {
-    let backup = fs::read_to_string(path)?;
+/**
+ * Open a file passed by the caller without following symlinks or creating it.
+ * The helper runs as root, so the file must be a regular file with a single link owned by the
+ * user that started it through pkexec.
+ */
+fn open_caller_file(path: &Path, options: &mut OpenOptions) -> Result<File> {
+    let file = options
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.nlink() != 1 || metadata.uid() != caller() {
+        return Err(anyhow!("{} is not a file of the caller", path.display()));
+    }
+    Ok(file)
+}
+
+/// Number of items in a line such as `these 3 derivations will be built:` or `this path will be fetched`.
+fn announced(line: &str, noun: &str, verb: &str) -> Option<u32> {
+    if !line.contains(verb) {
+        return None;
+    }
+    if line.starts_with(&format!("this {} ", noun)) {
+        return Some(1);
+    }
+    let mut words = line.strip_prefix("these ")?.split_whitespace();
+    let count = words.next()?.parse().ok()?;
+    words.next()?.starts_with(noun).then_some(count)
+}
 
-    let mut file = File::create(path)?;
-    write!(file, "{}", content)?;
+/// User that started the helper through pkexec, or the user running it directly.
+fn caller() -> u32 {
+    std::env::var("PKEXEC_UID")
+        .ok()
+        .and_then(|x| x.parse::<u32>().ok())
+        .unwrap_or_else(users::get_current_uid)
+}
 
-    if rebuild(args, generations).is_err() {
-        let mut file = File::create(path)?;
-        write!(file, "{}", &backup)?;
-        Err(anyhow!("Failed to rebuild"))
+/**
+ * The files `write-rebuild` may replace, resolved from the same nix-data configuration the module
+ * manager reads: the `modules.nix` next to the system configuration and the system flake.
+ */
+struct Config {
+    modules: PathBuf,
+    flake: Option<PathBuf>,
+}
+
+impl Config {
+    /// Read the nix-data configuration of the caller.
+    fn load() -> Result<Self> {
+        let home = users::get_user_by_uid(caller())
+            .map(|x| x.home_dir().to_path_buf())
+            .ok_or_else(|| anyhow!("Unknown user {}", caller()))?;
+        let path = home.join(NIX_DATA_CONFIG);
+        // Not blocking, in case the caller replaced it with a pipe
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .map_err(|err| anyhow!("Failed to open {}: {}", path.display(), err))?;
+        if !file.metadata()?.is_file() {
+            return Err(anyhow!("{} is not a file", path.display()));
+        }
+        let mut config = String::new();
+        file.read_to_string(&mut config)?;
+        let config = serde_json::from_str::<serde_json::Value>(&config)
+            .map_err(|err| anyhow!("Invalid {}: {}", path.display(), err))?;
+        let entry = |name: &str| config.get(name).and_then(|x| x.as_str()).map(PathBuf::from);
+        let modules = entry("systemconfig")
+            .and_then(|x| Some(x.parent()?.join("modules.nix")))
+            .ok_or_else(|| anyhow!("No system configuration set in {}", path.display()))?;
+        Ok(Config {
+            modules,
+            flake: entry("flake"),
+        })
+    }
+
+    fn managed(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.modules.as_path()).chain(self.flake.as_deref())
+    }
+}
+
+/**
+ * Check that `path` is a file managed by the module manager and `content` is valid Nix.
+ * Only the configured `modules.nix` and flake are allowed, and only as regular files.
+ */
+fn check_target(path: &str, content: &str, config: &Config) -> Result<()> {
+    let path = Path::new(path);
+    if !path.is_absolute() || !config.managed().any(|x| x == path) {
+        return Err(anyhow!("Refusing to write {}, it is not a managed file", path.display()));
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.file_type().is_file() {
+        return Err(anyhow!("Refusing to write {}, it is not a regular file", path.display()));
+    }
+    let errors = rnix::parse(content).errors();
+    if let Some(error) = errors.first() {
+        return Err(anyhow!("Refusing to write {}, content is not valid Nix: {}", path.display(), error));
+    }
+    Ok(())
+}
+
+/// Read secrets from the files they were passed in, returning where each should be stored.
+fn read_secrets(names: Vec<String>, files: Vec<String>) -> Result<Vec<(PathBuf, String)>> {
+    names
+        .into_iter()
+        .zip(files)
+        .map(|(name, file)| {
+            if name.is_empty()
+                || name.starts_with('.')
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+            {
+                return Err(anyhow!("Invalid secret name: {}", name));
+            }
+            let mut secret = String::new();
+            open_caller_file(Path::new(&file), OpenOptions::new().read(true))
+                .map_err(|err| anyhow!("Invalid secret file: {}", err))?
+                .read_to_string(&mut secret)?;
+            Ok((Path::new(SECRETS_DIR).join(name), secret))
+        })
+        .collect()
+}
+
+/// Suffix of the temporary file a write goes through before it is renamed into place
+const TEMP_SUFFIX: &str = ".smm-tmp";
+
+/// Marks a backup of a file replaced by `write-rebuild`, followed by a timestamp
+const BACKUP_MARKER: &str = ".smm-backup-";
+
+/// Marks a file created by `write-rebuild` that did not exist before, followed by a timestamp
+const CREATED_MARKER: &str = ".smm-created-";
+
+/// Append `suffix` to the file name of `path`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Flush the directory entry of `path` to disk, so a rename into it survives a crash.
+fn sync_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/**
+ * Replace `path` with `content` so that it is either fully written or left untouched.
+ * The content is written to a temporary file next to it, flushed to disk and renamed over the original.
+ * Ownership and permissions are taken from the file being replaced, if it exists.
+ */
+fn write_atomic(path: &Path, content: &[u8], mode: u32) -> Result<()> {
+    let owner = fs::symlink_metadata(path).ok().map(|x| (x.uid(), x.gid()));
+    let temp = with_suffix(path, TEMP_SUFFIX);
+    let _ = fs::remove_file(&temp);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(&temp)?;
+    let written = (|| {
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        if let Some((uid, gid)) = owner {
+            fchown(&file, Some(uid), Some(gid))?;
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err.into());
+    }
+    sync_parent(path)
+}
+
+/// An on-disk copy of a file taken before `write-rebuild` replaces it.
+struct Backup {
+    path: PathBuf,
+    backup: PathBuf,
+    existed: bool,
+}
+
+impl Backup {
+    /// Copy `path` next to itself, or leave a marker if it does not exist yet.
+    fn new(path: &Path, timestamp: u64) -> Result<Self> {
+        let existed = fs::symlink_metadata(path).is_ok();
+        let backup = if existed {
+            let backup = with_suffix(path, &format!("{}{}", BACKUP_MARKER, timestamp));
+            let mode = fs::metadata(path)?.permissions().mode() & 0o7777;
+            write_atomic(&backup, &fs::read(path)?, mode)?;
+            backup
+        } else {
+            let backup = with_suffix(path, &format!("{}{}", CREATED_MARKER, timestamp));
+            write_atomic(&backup, &[], 0o600)?;
+            backup
+        };
+        Ok(Backup {
+            path: path.to_path_buf(),
+            backup,
+            existed,
+        })
+    }
+
+    /// Put the original file back and remove the backup.
+    fn restore(&self) -> Result<()> {
+        restore_backup(&self.path, &self.backup, self.existed)?;
+        self.discard()
+    }
+
+    /// Remove the backup, keeping the file as it is now.
+    fn discard(&self) -> Result<()> {
+        fs::remove_file(&self.backup)?;
+        sync_parent(&self.backup)
+    }
+}
+
+/// Restore `path` from `backup`, or remove it if it was created by `write-rebuild`.
+fn restore_backup(path: &Path, backup: &Path, existed: bool) -> Result<()> {
+    if existed {
+        let mode = fs::metadata(backup)?.permissions().mode() & 0o7777;
+        write_atomic(path, &fs::read(backup)?, mode)
     } else {
-        Ok(())
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => sync_parent(path),
+        }
     }
 }
 
-fn rebuild(args: Vec<String>, generations: Option<u32>) -> Result<()> {
-    let mut cmd = Command::new("nixos-rebuild").args(args).spawn()?;
+/**
+ * Take the helper lock, failing if another invocation holds it.
+ * The lock is released when the returned file is closed, at the latest when the helper exits.
+ */
+fn lock() -> Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(LOCK_FILE)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == ErrorKind::WouldBlock {
+            return Err(anyhow!("Another rebuild is already running"));
+        }
+        return Err(err.into());
+    }
+    Ok(file)
+}
+
+/**
+ * Name of the file a temporary file or backup left behind by `write-rebuild` belongs to, with the
+ * timestamp of a backup and whether the file existed before it was taken.
+ */
+fn leftover(name: &str) -> Option<(&str, Option<(u64, bool)>)> {
+    if let Some(name) = name.strip_suffix(TEMP_SUFFIX) {
+        // Backups are written through a temporary file too
+        return Some((leftover(name).map_or(name, |(original, _)| original), None));
+    }
+    for (marker, existed) in [(BACKUP_MARKER, true), (CREATED_MARKER, false)] {
+        if let Some((original, timestamp)) = name.rsplit_once(marker) {
+            if let Ok(timestamp) = timestamp.parse::<u64>() {
+                return Some((original, Some((timestamp, existed))));
+            }
+        }
+    }
+    None
+}
+
+/**
+ * Restore files left behind by a `write-rebuild` that was interrupted.
+ * Backups only exist while a rebuild holds the lock, so any found on start belong to a run that never finished.
+ * If a file has several, the oldest one holds the state before any of them were taken.
+ * Only the configured managed files and secrets are touched. Failures are reported without stopping
+ * the requested operation.
+ */
+fn recover(config: Option<&Config>) {
+    let managed = |path: &Path| {
+        path.parent() == Some(Path::new(SECRETS_DIR))
+            || config.is_some_and(|config| config.managed().any(|x| x == path))
+    };
+    let mut dirs = config
+        .into_iter()
+        .flat_map(|x| x.managed())
+        .filter_map(|x| x.parent())
+        .map(|x| x.to_path_buf())
+        .collect::<Vec<_>>();
+    dirs.push(PathBuf::from(SECRETS_DIR));
+    dirs.dedup();
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                eprintln!("Failed to look for an interrupted rebuild in {}: {}", dir.display(), err);
+                continue;
+            }
+        };
+        let mut backups: BTreeMap<PathBuf, Vec<(u64, PathBuf, bool)>> = BTreeMap::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some((original, backup)) = leftover(&name) else {
+                continue;
+            };
+            let original = dir.join(original);
+            if !managed(&original) {
+                continue;
+            }
+            match backup {
+                Some((timestamp, existed)) => backups
+                    .entry(original)
+                    .or_default()
+                    .push((timestamp, entry.path(), existed)),
+                None => {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+        for (path, mut found) in backups {
+            found.sort();
+            eprintln!("Recovering {} from an interrupted rebuild", path.display());
+            if let Err(err) = recover_file(&path, &found) {
+                eprintln!("Failed to recover {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Restore `path` from the oldest of its backups and remove them all.
+fn recover_file(path: &Path, backups: &[(u64, PathBuf, bool)]) -> Result<()> {
+    let (_, backup, existed) = &backups[0];
+    restore_backup(path, backup, *existed)?;
+    for (_, backup, _) in backups {
+        fs::remove_file(backup)?;
+    }
+    sync_parent(path)
+}
+
+fn write_file(files: &[(String, String)], secrets: &[(PathBuf, String)], config: &Config, restore: bool, args: Vec<String>, generations: Option<u32>, progress: &mut Progress) -> Result<()> {
+    progress.phase("writing");
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if !secrets.is_empty() {
+        state_dir(SECRETS_DIR, 0o700)?;
+    }
+    let mut backups = vec![];
+    for path in files.iter().map(|(path, _)| Path::new(path)).chain(secrets.iter().map(|(path, _)| path.as_path())) {
+        match Backup::new(path, timestamp) {
+            Ok(backup) => backups.push(backup),
+            Err(err) => {
+                for backup in &backups {
+                    let _ = backup.discard();
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    // Only these create a generation that the new configuration belongs to
+    let activates = !restore && args.first().is_some_and(|x| x == "switch" || x == "boot");
+    let result = write_all(files, secrets).and_then(|_| {
+        if restore {
+            // `nixos-rebuild build` leaves a `result` link in the working directory
+            let workdir = std::env::temp_dir().join(format!("smm-build-{}", std::process::id()));
+            DirBuilder::new().mode(0o700).create(&workdir)?;
+            let result = rebuild_in(args, None, Some(&workdir), progress);
+            let _ = fs::remove_dir_all(&workdir);
+            result
+        } else {
+            rebuild(args, generations, progress)
+        }
+    });
+
+    // Backups are only removed once the files they protect are in a known good state
+    for backup in &backups {
+        if restore || result.is_err() {
+            backup.restore()?;
+        } else {
+            backup.discard()?;
+        }
+    }
+    if result.is_ok() && activates {
+        if let Err(err) = save_generation(files, config) {
+            eprintln!("Failed to save the module configuration of this generation: {}", err);
+        }
+    }
+    result.map_err(|err| anyhow!("Failed to rebuild: {}", err))
+}
+
+/// System generations that still exist, sorted by number, with the profile link of each.
+fn generations() -> Result<Vec<(u32, PathBuf)>> {
+    let profiles = Path::new(SYSTEM_PROFILE)
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid profile: {}", SYSTEM_PROFILE))?;
+    let mut generations = vec![];
+    for entry in fs::read_dir(profiles)? {
+        let entry = entry?;
+        if let Some(number) = generation_number(&entry.file_name().to_string_lossy()) {
+            generations.push((number, entry.path()));
+        }
+    }
+    generations.sort();
+    Ok(generations)
+}
+
+/// Number of a generation from its link name, such as `system-42-link`.
+fn generation_number(link: &str) -> Option<u32> {
+    link.strip_prefix("system-")?
+        .strip_suffix("-link")?
+        .parse()
+        .ok()
+}
+
+/// Generation the system profile currently points to.
+fn current_generation() -> Result<Option<u32>> {
+    let target = fs::read_link(SYSTEM_PROFILE)?;
+    Ok(target
+        .file_name()
+        .and_then(|x| generation_number(&x.to_string_lossy())))
+}
+
+fn list_generations() -> Result<()> {
+    let current = current_generation()?;
+    for (number, link) in generations()? {
+        let date = fs::symlink_metadata(&link)?.mtime();
+        let version = fs::read_to_string(link.join("nixos-version"))
+            .map(|x| x.trim().to_string())
+            .unwrap_or_default();
+        println!(
+            "{}\t{}\t{}\t{}",
+            number,
+            date,
+            version,
+            if Some(number) == current { "current" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/**
+ * Save the `modules.nix` and `flake.nix` that were just activated under the number of the current
+ * generation. The configured flake is saved even if it was not written, as it still lists the
+ * installed modules.
+ * Saved files of generations that have since been deleted are removed.
+ */
+fn save_generation(files: &[(String, String)], config: &Config) -> Result<()> {
+    let number = current_generation()?.ok_or_else(|| anyhow!("Unknown current generation"))?;
+    let written = |file: &Path| {
+        files
+            .iter()
+            .find(|(path, _)| Path::new(path) == file)
+            .map(|(_, content)| content.to_string())
+    };
+    let moduleconfig = written(&config.modules).ok_or_else(|| anyhow!("modules.nix was not written"))?;
+    let flake = match &config.flake {
+        Some(path) => Some(match written(path) {
+            Some(flake) => flake,
+            None => fs::read_to_string(path)?,
+        }),
+        None => None,
+    };
+    state_dir(GENERATIONS_DIR, 0o755)?;
+    write_atomic(
+        &Path::new(GENERATIONS_DIR).join(format!("{}.nix", number)),
+        moduleconfig.as_bytes(),
+        0o644,
+    )?;
+    if let Some(flake) = flake {
+        write_atomic(
+            &Path::new(GENERATIONS_DIR).join(format!("{}.flake.nix", number)),
+            flake.as_bytes(),
+            0o644,
+        )?;
+    }
+    let existing = generations()?
+        .into_iter()
+        .map(|(number, _)| number)
+        .collect::<Vec<_>>();
+    for entry in fs::read_dir(GENERATIONS_DIR)? {
+        let path = entry?.path();
+        let saved = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .and_then(|x| x.strip_suffix(".nix"))
+            .map(|x| x.strip_suffix(".flake").unwrap_or(x))
+            .and_then(|x| x.parse::<u32>().ok());
+        if saved.is_some_and(|x| !existing.contains(&x)) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Create a directory inside `STATE_DIR` with the given mode if it does not exist.
+ * `STATE_DIR` itself is created world-readable, so the modes of its subdirectories
+ * decide who can read them.
+ */
+fn state_dir(dir: &str, mode: u32) -> Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(STATE_DIR)?;
+    // Earlier versions created it together with the secrets directory, readable only by root
+    fs::set_permissions(STATE_DIR, fs::Permissions::from_mode(0o755))?;
+    match DirBuilder::new().mode(mode).create(dir) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Write the new managed files and secrets, keeping the permissions of existing files.
+fn write_all(files: &[(String, String)], secrets: &[(PathBuf, String)]) -> Result<()> {
+    for (path, content) in files {
+        let mode = fs::metadata(path)?.permissions().mode() & 0o7777;
+        write_atomic(Path::new(path), content.as_bytes(), mode)?;
+    }
+    for (path, secret) in secrets {
+        write_atomic(path, secret.as_bytes(), 0o600)?;
+    }
+    Ok(())
+}
+
+/// Error for a command that did not succeed, with its exit code or the signal that killed it.
+fn failure(command: &str, status: ExitStatus) -> anyhow::Error {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => anyhow!("{} failed with exit code {}", command, code),
+        (None, Some(signal)) => anyhow!("{} was killed by signal {}", command, signal),
+        (None, None) => anyhow!("{} failed: {}", command, status),
+    }
+}
+
+fn rebuild(args: Vec<String>, generations: Option<u32>, progress: &mut Progress) -> Result<()> {
+    rebuild_in(args, generations, None, progress)
+}
+
+fn rebuild_in(args: Vec<String>, generations: Option<u32>, workdir: Option<&Path>, progress: &mut Progress) -> Result<()> {
+    progress.phase("evaluating");
+    let mut cmd = Command::new("nixos-rebuild");
+    if let Some(workdir) = workdir {
+        cmd.current_dir(workdir);
+    }
+    let mut cmd = cmd.args(args).stderr(Stdio::piped()).spawn()?;
+    // Nix logs to stderr, which is passed on to the terminal while following the progress
+    if let Some(stderr) = cmd.stderr.take() {
+        for line in BufReader::new(stderr).split(b'\n') {
+            let line = String::from_utf8_lossy(&line?).to_string();
+            eprintln!("{}", line);
+            progress.track(&line);
+        }
+    }
     let x = cmd.wait()?;
     if !x.success() {
-        return Err(anyhow!("nixos-rebuild failed with exit code {}", x.code().unwrap()));
+        return Err(failure("nixos-rebuild", x));
     }
     if let Some(g) = generations {
         if g > 0 {
+            progress.phase("cleaning");
             let mut cmd = Command::new("nix-env")
                 .arg("--delete-generations")
                 .arg("-p")
-                .arg("/nix/var/nix/profiles/system")
+                .arg(SYSTEM_PROFILE)
                 .arg(&format!("+{}", g))
                 .spawn()?;
             let x = cmd.wait()?;
             if !x.success() {
-                return Err(anyhow!(
-                    "nix-env --delete-generations failed with exit code {}",
-                    x.code().unwrap()
-                ));
+                return Err(failure("nix-env --delete-generations", x));
             }
         }
     }
     Ok(())
 }
+
+/**
+ * Switch the system back to an earlier generation.
+ * Without a generation this is `nixos-rebuild switch --rollback`, otherwise the profile is pointed at
+ * the given generation and its configuration is activated.
+ */
+fn rollback(generation: Option<u32>, progress: &mut Progress) -> Result<()> {
+    let generation = match generation {
+        Some(generation) => generation,
+        None => return rebuild(vec![String::from("switch"), String::from("--rollback")], None, progress),
+    };
+    let x = Command::new("nix-env")
+        .arg("-p")
+        .arg(SYSTEM_PROFILE)
+        .arg("--switch-generation")
+        .arg(generation.to_string())
+        .spawn()?
+        .wait()?;
+    if !x.success() {
+        return Err(failure("nix-env --switch-generation", x));
+    }
+    progress.phase("activating");
+    let x = Command::new(Path::new(SYSTEM_PROFILE).join("bin/switch-to-configuration"))
+        .arg("switch")
+        .spawn()?
+        .wait()?;
+    if !x.success() {
+        return Err(failure("switch-to-configuration", x));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announced_counts_are_parsed() {
+        assert_eq!(announced("these 12 derivations will be built:", "derivation", "will be built"), Some(12));
+        assert_eq!(announced("this derivation will be built:", "derivation", "will be built"), Some(1));
+        assert_eq!(
+            announced("these 3 paths will be fetched (1.0 MiB download, 2.0 MiB unpacked):", "path", "will be fetched"),
+            Some(3)
+        );
+        assert_eq!(announced("this path will be fetched (0.1 MiB download):", "path", "will be fetched"), Some(1));
+    }
+
+    #[test]
+    fn unrelated_lines_are_not_announcements() {
+        assert_eq!(announced("these 3 paths will be fetched", "derivation", "will be built"), None);
+        assert_eq!(announced("these 3 derivations will be built:", "path", "will be fetched"), None);
+        assert_eq!(announced("these many derivations will be built:", "derivation", "will be built"), None);
+        assert_eq!(announced("building '/nix/store/a.drv'...", "derivation", "will be built"), None);
+        assert_eq!(announced("", "path", "will be fetched"), None);
+    }
+
+    /// Progress events written by `report`.
+    fn emitted(report: impl FnOnce(&mut Progress)) -> String {
+        static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "smm-helper-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new().create_new(true).write(true).open(&path).unwrap();
+        let mut progress = Progress {
+            file: Some(file),
+            ..Default::default()
+        };
+        report(&mut progress);
+        let events = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        events
+    }
+
+    /// Events emitted while following `lines` of `nixos-rebuild` output.
+    fn tracked(lines: &[&str]) -> Vec<String> {
+        emitted(|progress| {
+            for line in lines {
+                progress.track(line);
+            }
+        })
+        .lines()
+        .map(|x| x.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn build_and_download_steps_are_counted() {
+        assert_eq!(
+            tracked(&[
+                "building the system configuration...",
+                "these 2 derivations will be built:",
+                "  /nix/store/a.drv",
+                "this path will be fetched (1.0 MiB download, 2.0 MiB unpacked):",
+                "copying path '/nix/store/x' from 'https://cache.nixos.org'...",
+                "building '/nix/store/a.drv'...",
+                "building '/nix/store/b.drv'...",
+                "activating the configuration...",
+                "updating GRUB 2 menu...",
+            ]),
+            vec![
+                "building\t0\t2",
+                "downloading\t0\t1",
+                "downloading\t1\t1",
+                "building\t1\t2",
+                "building\t2\t2",
+                "activating",
+                "activating",
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_never_exceed_the_announced_total() {
+        assert_eq!(
+            tracked(&[
+                "building '/nix/store/a.drv'...",
+                "this derivation will be built:",
+                "building '/nix/store/b.drv'...",
+                "building '/nix/store/c.drv'...",
+            ]),
+            vec!["building\t0\t0", "building\t0\t1", "building\t1\t1", "building\t1\t1"]
+        );
+    }
+
+    #[test]
+    fn leftovers_name_the_file_they_belong_to() {
+        assert_eq!(leftover("modules.nix.smm-tmp"), Some(("modules.nix", None)));
+        assert_eq!(leftover("modules.nix.smm-backup-12"), Some(("modules.nix", Some((12, true)))));
+        assert_eq!(leftover("flake.nix.smm-created-7"), Some(("flake.nix", Some((7, false)))));
+        assert_eq!(leftover("modules.nix.smm-backup-12.smm-tmp"), Some(("modules.nix", None)));
+        assert_eq!(leftover("modules.nix.smm-backup-latest"), None);
+        assert_eq!(leftover("modules.nix"), None);
+    }
+
+    #[test]
+    fn failures_report_the_exit_code_or_signal() {
+        // Wait statuses as returned by waitpid
+        assert_eq!(
+            failure("nixos-rebuild", ExitStatus::from_raw(1 << 8)).to_string(),
+            "nixos-rebuild failed with exit code 1"
+        );
+        assert_eq!(
+            failure("nixos-rebuild", ExitStatus::from_raw(libc::SIGKILL)).to_string(),
+            "nixos-rebuild was killed by signal 9"
+        );
+    }
+
+    #[test]
+    fn emitted_fields_are_kept_on_one_line() {
+        assert_eq!(
+            emitted(|progress| progress.emit(&["error", "first\nsecond\tthird"])),
+            "error\tfirst second third\n"
+        );
+    }
+}
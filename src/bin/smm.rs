@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use nix_data::config::configfile::NixDataConfig;
+use snowflakeos_module_manager::{
+    config::LIBEXECDIR,
+    modules::{
+        configfile::{applychanges, unifieddiff, ModuleConfigFile},
+        load::{getcurrentoptions, loadmodules, LoadedModules},
+        roots::moduleroots,
+        snapshot::{pendingmodifications, ModuleSnapshot, PendingModification, Snapshot},
+        Module, ModuleOption, OptionData, PendingChange,
+    },
+};
+
+/// Manage SnowflakeOS modules from the command line.
+///
+/// `set` and `reset` stage changes to options, which are applied to the current modules.nix
+/// when it is shown or written. Use `diff` to review them and `apply` to write them and rebuild
+/// the system.
+#[derive(Parser, Debug)]
+#[command(name = "smm", version)]
+struct Cli {
+    /// Additional module root, searched before all others
+    #[arg(long = "modules-path", global = true)]
+    modules_path: Vec<PathBuf>,
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: SubCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum SubCommands {
+    /// List installed modules
+    List {
+        /// Also list modules that are available but not installed
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Show a module and the values of its options
+    Show {
+        /// Module id or name
+        module: String,
+    },
+    /// Print the value of an option
+    Get {
+        /// Option id, such as `modules.gnome.enable`
+        option: String,
+    },
+    /// Stage a new value for an option
+    Set {
+        /// Option id, such as `modules.gnome.enable`
+        option: String,
+        /// New value, such as `true`, some text, an enum value or `1,2,3`
+        value: String,
+    },
+    /// Stage removing an option so the module default applies
+    Reset {
+        /// Option id, such as `modules.gnome.enable`
+        option: String,
+    },
+    /// Show staged changes to modules.nix
+    Diff,
+    /// Write staged changes to modules.nix and rebuild the system
+    Apply,
+    /// Print all modules, option values and staged changes as JSON
+    Snapshot,
+}
+
+struct Session {
+    config: NixDataConfig,
+    flakepath: PathBuf,
+    modulefile: ModuleConfigFile,
+    stagedpath: PathBuf,
+    loaded: LoadedModules,
+    json: bool,
+}
+
+fn main() {
+    pretty_env_logger::init();
+    let cli = Cli::parse();
+    if let Err(e) = run(cli) {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let session = load(&cli.modules_path, cli.json)?;
+    match cli.command {
+        SubCommands::List { all } => list(&session, all),
+        SubCommands::Show { module } => show(&session, &module),
+        SubCommands::Get { option } => get(&session, &option),
+        SubCommands::Set { option, value } => set(&session, &option, Some(&value)),
+        SubCommands::Reset { option } => set(&session, &option, None),
+        SubCommands::Diff => diff(&session),
+        SubCommands::Apply => apply(&session),
+        SubCommands::Snapshot => snapshot(&session),
+    }
+}
+
+fn load(overrides: &[PathBuf], json: bool) -> Result<Session> {
+    let config = nix_data::config::configfile::getconfig().context("Failed to load config")?;
+    let flakepath = config
+        .flake
+        .as_ref()
+        .map(PathBuf::from)
+        .context("Failed to get flake path")?;
+    let modulefile = ModuleConfigFile::from_config(&config)?;
+    let stagedpath = stagedpath()?;
+    let loaded = loadmodules(&flakepath, &moduleroots(overrides))?;
+    if !json {
+        for diagnostic in &loaded.diagnostics {
+            eprintln!("warning: {}", diagnostic);
+        }
+    }
+    Ok(Session {
+        config,
+        flakepath,
+        modulefile,
+        stagedpath,
+        loaded,
+        json,
+    })
+}
+
+/// Location of the staged option changes, `$XDG_STATE_HOME/snowflakeos-module-manager/changes.json`.
+fn stagedpath() -> Result<PathBuf> {
+    let statehome = env::var_os("XDG_STATE_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .context("Neither XDG_STATE_HOME nor HOME is set")?;
+    Ok(statehome
+        .join("snowflakeos-module-manager")
+        .join("changes.json"))
+}
+
+impl Session {
+    fn is_staged(&self) -> bool {
+        self.stagedpath.exists()
+    }
+
+    /// Staged option changes, by option id.
+    fn staged(&self) -> Result<HashMap<String, PendingChange>> {
+        if !self.is_staged() {
+            return Ok(HashMap::new());
+        }
+        let staged = fs::read_to_string(&self.stagedpath)?;
+        serde_json::from_str(&staged).with_context(|| {
+            format!("Failed to read staged changes from {}", self.stagedpath.to_string_lossy())
+        })
+    }
+
+    fn stage(&self, changes: &HashMap<String, PendingChange>) -> Result<()> {
+        if changes.is_empty() {
+            if self.is_staged() {
+                fs::remove_file(&self.stagedpath)?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = self.stagedpath.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.stagedpath, serde_json::to_string_pretty(changes)?)?;
+        Ok(())
+    }
+
+    /// The system modules.nix and the result of applying the staged changes to it.
+    fn stagedconfig(&self) -> Result<(String, String)> {
+        let current = self.modulefile.read()?;
+        let staged = applychanges(&current, &self.staged()?)?;
+        Ok((current, staged))
+    }
+
+    /// Option values set in the system modules.nix.
+    fn systemoptions(&self) -> Result<HashMap<String, ModuleOption>> {
+        getcurrentoptions(&self.modulefile, &self.loaded.modules)
+    }
+
+    /// Option values with the staged changes applied.
+    fn currentoptions(&self) -> Result<HashMap<String, ModuleOption>> {
+        let mut options = self.systemoptions()?;
+        for (id, change) in self.staged()? {
+            match change {
+                PendingChange::Set(value) => {
+                    options.insert(id, value);
+                }
+                PendingChange::Unset => {
+                    options.remove(&id);
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Staged changes compared to the system modules.nix.
+    fn pending(&self) -> Result<Vec<PendingModification>> {
+        if !self.is_staged() {
+            return Ok(vec![]);
+        }
+        Ok(pendingmodifications(&self.systemoptions()?, &self.currentoptions()?))
+    }
+
+    fn findoption(&self, id: &str) -> Result<(&Module, &OptionData)> {
+        self.loaded
+            .modules
+            .iter()
+            .find_map(|module| {
+                module
+                    .config
+                    .options
+                    .iter()
+                    .find(|option| option.id == id)
+                    .map(|option| (module, option))
+            })
+            .with_context(|| format!("Option {} not found in any installed module", id))
+    }
+}
+
+fn printjson<T: serde::Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn list(session: &Session, all: bool) -> Result<()> {
+    if session.json {
+        let current = session.currentoptions()?;
+        let mut modules = session
+            .loaded
+            .modules
+            .iter()
+            .map(|module| ModuleSnapshot::new(module, true, &current))
+            .collect::<Vec<_>>();
+        if all {
+            modules.extend(
+                session
+                    .loaded
+                    .available
+                    .iter()
+                    .map(|module| ModuleSnapshot::new(module, false, &current)),
+            );
+        }
+        return printjson(&modules);
+    }
+    for module in &session.loaded.modules {
+        println!("{}\t{}\t{}", module.config.id, module.config.version, module.config.name);
+    }
+    if all {
+        for module in &session.loaded.available {
+            println!(
+                "{}\t{}\t{} (not installed)",
+                module.config.id, module.config.version, module.config.name
+            );
+        }
+    }
+    Ok(())
+}
+
+fn show(session: &Session, id: &str) -> Result<()> {
+    let module = session
+        .loaded
+        .modules
+        .iter()
+        .chain(session.loaded.available.iter())
+        .find(|x| x.config.id == id || x.name == id || x.config.name == id)
+        .with_context(|| format!("Module {} not found", id))?;
+    let current = session.currentoptions()?;
+    if session.json {
+        let installed = session.loaded.modules.iter().any(|x| x.path == module.path);
+        return printjson(&ModuleSnapshot::new(module, installed, &current));
+    }
+    println!("{} {}", module.config.name, module.config.version);
+    if let Some(description) = &module.config.description {
+        println!("{}", description);
+    }
+    println!("Loaded from {}", module.root);
+    println!();
+    for option in &module.config.options {
+        match current.get(&option.id) {
+            Some(value) => println!("{} = {}", option.id, value.value()),
+            None => println!(
+                "{} = {} (default)",
+                option.id,
+                option.op_type.default_value().value()
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn get(session: &Session, id: &str) -> Result<()> {
+    let (module, option) = session.findoption(id)?;
+    let current = session.currentoptions()?;
+    if session.json {
+        let snapshot = ModuleSnapshot::new(module, true, &current);
+        return printjson(&snapshot.options.iter().find(|x| x.id == id));
+    }
+    match current.get(id) {
+        Some(value) => println!("{}", value.value()),
+        None => println!("{}", option.op_type.default_value().value()),
+    }
+    Ok(())
+}
+
+/**
+ * Stage a change to an option, dropping it if the system modules.nix already has that value.
+ * The value is checked by `Module::checkoption`, like any value written by `Module::setoption`.
+ */
+fn set(session: &Session, id: &str, value: Option<&str>) -> Result<()> {
+    let (module, option) = session.findoption(id)?;
+    let current = session.systemoptions()?;
+    let mut changes = session.staged()?;
+    match value {
+        Some(value) => {
+            let value = option.op_type.parse_value(value)?;
+            module.checkoption(option, &value.value())?;
+            if current.get(id) == Some(&value) {
+                changes.remove(id);
+            } else {
+                changes.insert(id.to_string(), PendingChange::Set(value));
+            }
+        }
+        None => {
+            if current.contains_key(id) {
+                changes.insert(id.to_string(), PendingChange::Unset);
+            } else {
+                changes.remove(id);
+            }
+        }
+    }
+    session.stage(&changes)
+}
+
+fn diff(session: &Session) -> Result<()> {
+    if session.json {
+        return printjson(&session.pending()?);
+    }
+    if !session.is_staged() {
+        return Ok(());
+    }
+    let (current, staged) = session.stagedconfig()?;
+    let path = session.modulefile.path().to_string_lossy();
+    print!("{}", unifieddiff(&current, &staged, &path, "staged"));
+    Ok(())
+}
+
+fn apply(session: &Session) -> Result<()> {
+    if !session.is_staged() {
+        println!("No staged changes");
+        return Ok(());
+    }
+    // Changes are applied to the file as it is now, keeping edits made since they were staged
+    let (_, staged) = session.stagedconfig()?;
+    let helper = format!("{}/smm-helper", LIBEXECDIR);
+    let mut cmd = if users::get_effective_uid() == 0 {
+        Command::new(&helper)
+    } else {
+        let mut cmd = Command::new("pkexec");
+        cmd.arg(&helper);
+        cmd
+    };
+    cmd.arg("write-rebuild")
+        .arg("--content")
+        .arg(staged)
+        .arg("--path")
+        .arg(session.modulefile.path());
+    if let Some(generations) = session.config.generations {
+        cmd.arg("--generations").arg(generations.to_string());
+    }
+    cmd.arg("--")
+        .arg("switch")
+        .arg("--flake")
+        .arg(&session.flakepath);
+    let status = cmd.status().context("Failed to run smm-helper")?;
+    if !status.success() {
+        anyhow::bail!("Rebuild failed, staged changes were kept");
+    }
+    fs::remove_file(&session.stagedpath)?;
+    Ok(())
+}
+
+fn snapshot(session: &Session) -> Result<()> {
+    printjson(&Snapshot::new(
+        &session.loaded.modules,
+        &session.loaded.available,
+        &session.currentoptions()?,
+        session.pending()?,
+        &session.loaded.diagnostics,
+    ))
+}
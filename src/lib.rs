@@ -1,5 +1,3 @@
 pub mod modules;
 pub mod ui;
 pub mod config;
-
-const MODULES_CONFIG: &str = "/home/victor/Documents/threadripperwork/nix2/systems/x86_64-linux/snowflakeos/modules.nix";
\ No newline at end of file
@@ -1,9 +1,10 @@
+use std::path::PathBuf;
 
 use gtk::{glib};
 
 use log::error;
 use relm4::*;
-use snowflakeos_module_manager::ui::{window::{AppModel, AppInit}, load::load};
+use snowflakeos_module_manager::{ui::{window::{AppModel, AppInit}, load::load}, modules::roots::moduleroots};
 fn main() {
     gtk::init().unwrap();
     pretty_env_logger::init();
@@ -19,8 +20,25 @@ fn main() {
     // app.set_resource_base_path(Some("/dev/vlinkz/NixSoftwareCenter"));
     let app = RelmApp::new("org.snowflakeos.modulemanager");
 
-    match load() {
-        Ok(load) => app.run::<AppModel>(AppInit { load }),
+    // `--modules-path <dir>` adds a module root with the highest precedence.
+    // Remaining arguments are handed to GTK.
+    let mut args = vec![];
+    let mut overrides = vec![];
+    let mut argv = std::env::args();
+    while let Some(arg) = argv.next() {
+        if arg == "--modules-path" {
+            if let Some(path) = argv.next() {
+                overrides.push(PathBuf::from(path));
+            }
+        } else if let Some(path) = arg.strip_prefix("--modules-path=") {
+            overrides.push(PathBuf::from(path));
+        } else {
+            args.push(arg);
+        }
+    }
+
+    match load(moduleroots(&overrides)) {
+        Ok(load) => app.run_with_args::<AppModel, _>(AppInit { load }, &args),
         Err(e) => {
             error!("Failed to load: {}", e);
             std::process::exit(1);
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use nix_data::config::configfile::NixDataConfig;
+use rnix::{SyntaxKind, SyntaxNode};
+
+use super::{nixstring, PendingChange};
+
+/// The `modules.nix` file that holds module option values.
+/// It is located next to the system `default.nix` configured in `NixDataConfig`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleConfigFile {
+    path: PathBuf,
+}
+
+impl ModuleConfigFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Resolve `modules.nix` from the `systemconfig` entry of `NixDataConfig`.
+    pub fn from_config(config: &NixDataConfig) -> Result<Self> {
+        let path = Path::new(config.systemconfig.as_deref().context("systemconfig")?)
+            .parent()
+            .context("systemconfig parent")?
+            .join("modules.nix");
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&self) -> Result<String> {
+        fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.to_string_lossy()))
+    }
+
+    fn write(&self, content: &str) -> Result<()> {
+        fs::write(&self.path, nixpkgs_fmt::reformat_string(content))
+            .with_context(|| format!("Failed to write {}", self.path.to_string_lossy()))
+    }
+
+    /// Set `opt` to the Nix expression `value`.
+    pub fn write_option(&self, opt: &str, value: &str) -> Result<()> {
+        let moduleconfig = self.read()?;
+        self.write(&nix_editor::write::write(&moduleconfig, opt, value)?)
+    }
+
+    /// Remove `opt` from the file so that the module default applies.
+    pub fn deref_option(&self, opt: &str) -> Result<()> {
+        let moduleconfig = self.read()?;
+        self.write(&nix_editor::write::deref(&moduleconfig, opt)?)
+    }
+}
+
+/**
+ * Apply pending changes to the content of `modules.nix`, returning the reformatted result.
+ * This is exactly what is written when the changes are applied.
+ * Options whose attributes are also defined one by one, such as `environment.variables.FOO`
+ * next to `environment.variables`, are not changed, as the whole value would not replace them.
+ */
+pub fn applychanges(moduleconfig: &str, changes: &HashMap<String, PendingChange>) -> Result<String> {
+    let mut attributes = changes.keys().collect::<Vec<_>>();
+    attributes.sort();
+    let mut output = moduleconfig.to_string();
+    for attribute in attributes {
+        let nested = nesteddefinitions(&output, attribute);
+        if !nested.is_empty() {
+            anyhow::bail!(
+                "Failed to update {}, it is also set per entry in modules.nix: {}",
+                attribute,
+                nested.join(", ")
+            );
+        }
+        output = match &changes[attribute] {
+            PendingChange::Set(value) => nix_editor::write::write(&output, attribute, &value.value()),
+            PendingChange::Unset => nix_editor::write::deref(&output, attribute),
+        }
+        .with_context(|| format!("Failed to update {}", attribute))?;
+    }
+    Ok(nixpkgs_fmt::reformat_string(&output))
+}
+
+/**
+ * Attributes below `attribute` that `moduleconfig` defines on their own, such as `a.b.c` for `a.b`.
+ * Entries of an attribute set assigned to `attribute` as a whole are part of its value instead.
+ */
+fn nesteddefinitions(moduleconfig: &str, attribute: &str) -> Vec<String> {
+    let prefix = attribute.split('.').collect::<Vec<_>>();
+    let below = |path: &[String]| {
+        path.len() > prefix.len() && path.iter().zip(&prefix).all(|(a, b)| a == b)
+    };
+    rnix::parse(moduleconfig)
+        .node()
+        .descendants()
+        .filter(|x| x.kind() == SyntaxKind::NODE_KEY_VALUE)
+        .filter(|x| {
+            !x.ancestors()
+                .skip(1)
+                .filter(|x| x.kind() == SyntaxKind::NODE_KEY_VALUE)
+                .any(|x| definitionpath(&x) == prefix)
+        })
+        .map(|x| definitionpath(&x))
+        .filter(|path| below(path))
+        .map(|path| path.join("."))
+        .collect()
+}
+
+/// Full attribute path a `key = value;` binding defines, including the keys of enclosing bindings.
+fn definitionpath(keyvalue: &SyntaxNode) -> Vec<String> {
+    let mut path = vec![];
+    for binding in keyvalue
+        .ancestors()
+        .filter(|x| x.kind() == SyntaxKind::NODE_KEY_VALUE)
+    {
+        let key = binding
+            .children()
+            .find(|x| x.kind() == SyntaxKind::NODE_KEY)
+            .map(|key| {
+                key.children()
+                    .map(|x| {
+                        let text = x.text().to_string();
+                        match x.kind() {
+                            SyntaxKind::NODE_STRING => nixstring::unquote(&text).unwrap_or(text),
+                            _ => text,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        path.splice(0..0, key);
+    }
+    path
+}
+
+/// Unified diff from `current` to `new`, both being the content of the file at `path`.
+/// `label` describes the new content in the header, such as `staged`.
+pub fn unifieddiff(current: &str, new: &str, path: &str, label: &str) -> String {
+    textdiff(current, new, path, &format!("{} ({})", path, label))
+}
+
+/// Unified diff from `old` to `new`, with `oldlabel` and `newlabel` naming them in the header.
+pub fn textdiff(old: &str, new: &str, oldlabel: &str, newlabel: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(oldlabel, newlabel)
+        .to_string()
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+/// Flake attribute listing the NixOS modules imported into the system.
+pub const FLAKE_MODULES_ATTR: &str = "outputs.systems.modules.nixos";
+
+/**
+ * Add or remove modules from the flake module list.
+ * `changes` maps `Module::flakeattr` to whether the module should be installed.
+ */
+pub fn setmodulesinstalled(flake: &str, changes: &HashMap<String, bool>) -> Result<String> {
+    let mut add = vec![];
+    let mut remove = vec![];
+    for (flakeattr, install) in changes {
+        if *install {
+            add.push(flakeattr.to_string());
+        } else {
+            remove.push(flakeattr.to_string());
+        }
+    }
+    add.sort();
+    remove.sort();
+
+    let mut output = flake.to_string();
+    if !add.is_empty() {
+        output = nix_editor::write::addtoarr(&output, FLAKE_MODULES_ATTR, add)?;
+    }
+    if !remove.is_empty() {
+        output = nix_editor::write::rmarr(&output, FLAKE_MODULES_ATTR, remove)?;
+    }
+    Ok(nixpkgs_fmt::reformat_string(&output))
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::Result;
+
+use super::{
+    flake::FLAKE_MODULES_ATTR, load::readoptions, snapshot::pendingmodifications, Module,
+    ModuleOption, PendingChange,
+};
+
+/// Directory `smm-helper` saves the `modules.nix` and `flake.nix` of each generation in,
+/// readable by everyone
+pub const GENERATIONS_DIR: &str = "/var/lib/snowflakeos-module-manager/generations";
+
+/// A system generation as listed by `smm-helper list-generations`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub number: u32,
+    /// Creation time as a Unix timestamp
+    pub date: i64,
+    pub version: String,
+    pub current: bool,
+}
+
+/// Parse the output of `smm-helper list-generations`, newest generation first.
+pub fn parsegenerations(output: &str) -> Vec<Generation> {
+    let mut generations = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(Generation {
+                number: fields.next()?.parse().ok()?,
+                date: fields.next()?.parse().ok()?,
+                version: fields.next().unwrap_or_default().to_string(),
+                current: fields.next() == Some("current"),
+            })
+        })
+        .collect::<Vec<_>>();
+    generations.sort_by_key(|x| std::cmp::Reverse(x.number));
+    generations
+}
+
+/// Path of the saved `modules.nix` of a generation.
+pub fn generationpath(number: u32) -> PathBuf {
+    PathBuf::from(GENERATIONS_DIR).join(format!("{}.nix", number))
+}
+
+/// The `modules.nix` that was active in a generation, if it was saved when the generation was built.
+pub fn generationconfig(number: u32) -> Option<String> {
+    fs::read_to_string(generationpath(number)).ok()
+}
+
+/// The `flake.nix` that was active in a generation, listing the modules that were installed.
+/// Generations saved by earlier versions only have their `modules.nix`.
+pub fn generationflake(number: u32) -> Option<String> {
+    fs::read_to_string(PathBuf::from(GENERATIONS_DIR).join(format!("{}.flake.nix", number))).ok()
+}
+
+/**
+ * Pending changes that turn the `current` options into those set in `moduleconfig`.
+ * Options that are not set in `moduleconfig` are unset.
+ */
+pub fn restorechanges(
+    current: &HashMap<String, ModuleOption>,
+    moduleconfig: &str,
+    modules: &[Module],
+) -> HashMap<String, PendingChange> {
+    let restored = readoptions(moduleconfig, modules);
+    pendingmodifications(current, &restored)
+        .into_iter()
+        .map(|x| {
+            let change = match x.new {
+                Some(value) => PendingChange::Set(value),
+                None => PendingChange::Unset,
+            };
+            (x.id, change)
+        })
+        .collect()
+}
+
+/**
+ * Module changes that install the modules listed in the saved `flake` and remove the others,
+ * mapping `Module::flakeattr` to whether the module should be installed.
+ * Modules that are no longer in any module root can not be restored and are skipped.
+ */
+pub fn restoremodules(
+    installed: &[Module],
+    available: &[Module],
+    flake: &str,
+) -> Result<HashMap<String, bool>> {
+    let restored = nix_editor::read::getarrvals(flake, FLAKE_MODULES_ATTR)?;
+    let remove = installed
+        .iter()
+        .filter(|x| !restored.contains(&x.flakeattr))
+        .map(|x| (x.flakeattr.to_string(), false));
+    let add = available
+        .iter()
+        .filter(|x| restored.contains(&x.flakeattr))
+        .map(|x| (x.flakeattr.to_string(), true));
+    Ok(remove.chain(add).collect())
+}
@@ -1,93 +1,151 @@
-use anyhow::{Context, Result};
-use log::debug;
-use nix_data::config::configfile::NixDataConfig;
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
     path::{Path, PathBuf},
 };
 
-use crate::modules::{ModuleData, OptionType};
+use crate::modules::{ModuleData, OptionType, PathFormat};
 
-use super::{Module, ModuleOption};
+use super::{configfile::ModuleConfigFile, flake::FLAKE_MODULES_ATTR, nixstring, roots::ModuleRoot, Module, ModuleOption};
 
-pub fn loadmodules(flakepath: &Path) -> Result<Vec<Module>> {
-    // Iterate over all directories and subdirectories in the `basedir/modules` directory
-    // and return a vector of `Module`s based on finding a `default.nix` file in the directory.
+/// A problem encountered while loading a module, such as an unreadable or invalid `module.yml`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LoadDiagnostic {
+    pub file: PathBuf,
+    /// One-based line reported by `serde_yaml`
+    pub line: Option<usize>,
+    /// One-based column reported by `serde_yaml`
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LoadDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.to_string_lossy())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadedModules {
+    /// Modules listed in `outputs.systems.modules.nixos` of the flake
+    pub modules: Vec<Module>,
+    /// Modules found in a module root but not added to the flake
+    pub available: Vec<Module>,
+    pub diagnostics: Vec<LoadDiagnostic>,
+}
+
+pub fn loadmodules(flakepath: &Path, roots: &[ModuleRoot]) -> Result<LoadedModules> {
+    // Iterate over all directories and subdirectories in every module root
+    // and return a vector of `Module`s based on finding a `module.yml` file in the directory.
+    // Roots are ordered by precedence, so the first root containing a module id wins.
 
     let mut modules: Vec<Module> = Vec::new();
-    let modulepath = Path::new("/etc/snowflakeos-modules");
+    let mut available: Vec<Module> = Vec::new();
+    let mut diagnostics: Vec<LoadDiagnostic> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
 
     let flakefile = fs::read_to_string(flakepath)?;
     let installed_modules =
-        nix_editor::read::getarrvals(&flakefile, "outputs.systems.modules.nixos")?;
+        nix_editor::read::getarrvals(&flakefile, FLAKE_MODULES_ATTR)?;
 
-    for entry in walkdir::WalkDir::new(modulepath).into_iter().flatten() {
-        let path = entry.path();
-        if path.is_dir() {
-            let moduleconfig = path.join("module.yml");
-            if moduleconfig.exists() {
-                // Path from /etc/snowflakeos-modules joined by '/'
-                let mut moduleid = path
-                    .strip_prefix(modulepath)
-                    .unwrap_or(path)
-                    .iter()
-                    .map(|x| x.to_string_lossy())
-                    .collect::<Vec<_>>()
-                    .join("/");
-                if moduleid.contains("/") {
-                    moduleid = format!("\"{}\"", moduleid);
-                }
+    for root in roots {
+        let modulepath = root.path.as_path();
+        if !modulepath.is_dir() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(modulepath).into_iter().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                let moduleconfig = path.join("module.yml");
+                if moduleconfig.exists() {
+                    // Path from the module root joined by '/'
+                    let mut moduleid = path
+                        .strip_prefix(modulepath)
+                        .unwrap_or(path)
+                        .iter()
+                        .map(|x| x.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    if moduleid.contains('/') {
+                        moduleid = format!("\"{}\"", moduleid);
+                    }
+                    // A broken module still shadows the same id in lower precedence roots
+                    if !seen.insert(moduleid.to_string()) {
+                        debug!(
+                            "Module {} in {} is shadowed by a higher precedence root",
+                            moduleid,
+                            modulepath.to_string_lossy()
+                        );
+                        continue;
+                    }
 
-                if let Some(config_text) = fs::read_to_string(&moduleconfig)
-                    .ok()
-                    .and_then(|config_str| serde_yaml::from_str(&config_str).ok())
-                {
-                    let config: Option<ModuleData> =
-                        moduleconfig.exists().then(|| config_text).flatten();
+                    let config_str = match fs::read_to_string(&moduleconfig) {
+                        Ok(config_str) => config_str,
+                        Err(e) => {
+                            warn!("Failed to read {}: {}", moduleconfig.to_string_lossy(), e);
+                            diagnostics.push(LoadDiagnostic {
+                                file: moduleconfig,
+                                line: None,
+                                column: None,
+                                message: e.to_string(),
+                            });
+                            continue;
+                        }
+                    };
+                    let config: ModuleData = match serde_yaml::from_str(&config_str) {
+                        Ok(config) => config,
+                        Err(e) => {
+                            warn!("Failed to parse {}: {}", moduleconfig.to_string_lossy(), e);
+                            let location = e.location();
+                            diagnostics.push(LoadDiagnostic {
+                                file: moduleconfig,
+                                line: location.as_ref().map(|x| x.line()),
+                                column: location.as_ref().map(|x| x.column()),
+                                message: e.to_string(),
+                            });
+                            continue;
+                        }
+                    };
                     debug!("Loading config: {:#?}", config);
 
-                    if let Some(config) = config {
-                        let module = Module {
-                            name: path
-                                .file_name()
-                                .unwrap_or_default()
-                                .to_string_lossy()
-                                .to_string(),
-                            path: path.to_path_buf(),
-                            config: config.clone(),
-                        };
-                        if installed_modules
-                            .contains(&format!("{}.nixosModules.{}", config.flake, moduleid))
-                        {
-                            modules.push(module);
-                        }
+                    let flakeattr = format!("{}.nixosModules.{}", config.flake, moduleid);
+                    let module = Module {
+                        name: path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        path: path.to_path_buf(),
+                        root: root.clone(),
+                        flakeattr: flakeattr.to_string(),
+                        config,
+                    };
+                    if installed_modules.contains(&flakeattr) {
+                        modules.push(module);
+                    } else {
+                        available.push(module);
                     }
                 }
             }
         }
     }
     modules.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(modules)
-}
-
-/**
- * Load current module configuration in `modules.nix`, located next to the `default.nix` file.
- */
-pub fn loadmoduleconfig(config: &NixDataConfig) -> Result<String> {
-    let modulesnix = Path::new(&config.systemconfig.clone().context("systemconfig")?)
-        .parent()
-        .context("systemconfig parent")?
-        .join("modules.nix");
-    fs::read_to_string(modulesnix).context("modules.nix")
-}
-
-pub fn getmodulepath(config: &NixDataConfig) -> Result<PathBuf> {
-    let modulesnix = Path::new(&config.systemconfig.clone().context("systemconfig")?)
-        .parent()
-        .context("systemconfig parent")?
-        .join("modules.nix");
-    Ok(modulesnix)
+    available.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(LoadedModules {
+        modules,
+        available,
+        diagnostics,
+    })
 }
 
 /**
@@ -95,15 +153,14 @@ pub fn getmodulepath(config: &NixDataConfig) -> Result<PathBuf> {
  * Should be a HashMap<String, String>?
  */
 pub fn getcurrentoptions(
-    config: &NixDataConfig,
+    modulefile: &ModuleConfigFile,
     modules: &[Module],
 ) -> Result<HashMap<String, ModuleOption>> {
-    let modulesnix = Path::new(&config.systemconfig.clone().context("systemconfig")?)
-        .parent()
-        .context("systemconfig parent")?
-        .join("modules.nix");
-    let moduletext = fs::read_to_string(modulesnix).context("modules.nix")?;
+    Ok(readoptions(&modulefile.read()?, modules))
+}
 
+/// Read the options of `modules` set in `moduletext`, the content of a `modules.nix`.
+pub fn readoptions(moduletext: &str, modules: &[Module]) -> HashMap<String, ModuleOption> {
     let options = modules
         .iter()
         .map(|x| x.config.options.clone())
@@ -113,7 +170,7 @@ pub fn getcurrentoptions(
     let mut output = HashMap::new();
     for option in options {
         let attribute = option.id;
-        let string_value = nix_editor::read::readvalue(&moduletext, &attribute);
+        let string_value = nix_editor::read::readvalue(moduletext, &attribute);
 
         if let Ok(string_value) = string_value {
             match option.op_type {
@@ -126,16 +183,8 @@ pub fn getcurrentoptions(
                     output.insert(attribute, ModuleOption::Switch { value });
                 }
                 OptionType::Text { .. } => {
-                    let value = string_value
-                        .strip_prefix('"')
-                        .and_then(|x| x.strip_suffix('"'));
-                    if let Some(value) = value {
-                        output.insert(
-                            attribute,
-                            ModuleOption::Text {
-                                value: value.to_string(),
-                            },
-                        );
+                    if let Some(value) = nixstring::unquote(&string_value) {
+                        output.insert(attribute, ModuleOption::Text { value });
                     }
                 }
                 OptionType::Enum { options, .. } => {
@@ -152,7 +201,7 @@ pub fn getcurrentoptions(
                     );
                 }
                 OptionType::NumberList { .. } => {
-                    if let Ok(arr) = nix_editor::read::getarrvals(&moduletext, &attribute) {
+                    if let Ok(arr) = nix_editor::read::getarrvals(moduletext, &attribute) {
                         let numbers = arr
                             .iter()
                             .filter_map(|x| x.parse::<u32>().ok())
@@ -160,8 +209,68 @@ pub fn getcurrentoptions(
                         output.insert(attribute, ModuleOption::NumberList { value: numbers });
                     }
                 }
+                OptionType::StringList { .. } => {
+                    // Lists with entries other than plain strings can not be edited without losing them
+                    let strings = nix_editor::read::getarrvals(moduletext, &attribute)
+                        .ok()
+                        .and_then(|arr| {
+                            arr.iter()
+                                .map(|x| nixstring::unquote(x))
+                                .collect::<Option<Vec<_>>>()
+                        });
+                    if let Some(strings) = strings {
+                        output.insert(attribute, ModuleOption::StringList { value: strings });
+                    }
+                }
+                OptionType::MultiEnum { options, .. } => {
+                    // Every entry must be one of the choices, or it would be lost on the next write
+                    let values = nix_editor::read::getarrvals(moduletext, &attribute)
+                        .ok()
+                        .and_then(|arr| {
+                            arr.iter()
+                                .map(|x| nixstring::unquote(x).filter(|x| options.contains_key(x)))
+                                .collect::<Option<Vec<_>>>()
+                        });
+                    if let Some(values) = values {
+                        output.insert(attribute, ModuleOption::MultiEnum { value: values });
+                    }
+                }
+                OptionType::Map { value_type, .. } => {
+                    let value = nixstring::unquote_attrs(&string_value).and_then(|entries| {
+                        entries
+                            .into_iter()
+                            .map(|(name, value)| Some((name, value_type.decode(&value)?)))
+                            .collect::<Option<BTreeMap<_, _>>>()
+                    });
+                    if let Some(value) = value {
+                        output.insert(attribute, ModuleOption::Map { value });
+                    }
+                }
+                OptionType::Secret { .. } => {
+                    if let Some(path) = nixstring::unquote(&string_value) {
+                        output.insert(attribute, ModuleOption::Secret { path, secret: None });
+                    }
+                }
+                OptionType::Path { format, .. } => {
+                    if let Some(value) = nixstring::unquote(&string_value)
+                        .or_else(|| nixstring::unpath(&string_value))
+                    {
+                        output.insert(
+                            attribute,
+                            ModuleOption::Path {
+                                value,
+                                literal: format == PathFormat::Path,
+                            },
+                        );
+                    }
+                }
+                OptionType::Integer { .. } => {
+                    if let Ok(value) = string_value.parse::<i64>() {
+                        output.insert(attribute, ModuleOption::Integer { value });
+                    }
+                }
             }
         }
     }
-    Ok(output)
+    output
 }
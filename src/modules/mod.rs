@@ -1,14 +1,29 @@
-use std::{path::PathBuf, collections::HashMap};
+use std::{path::PathBuf, collections::{BTreeMap, HashMap}};
 
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use self::{roots::ModuleRoot, secret::SecretValue};
+
+pub mod configfile;
+pub mod flake;
+pub mod generations;
 pub mod load;
 pub mod modify;
+pub mod nixstring;
+pub mod progress;
+pub mod roots;
+pub mod secret;
+pub mod snapshot;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Module {
     pub name: String,
     pub path: PathBuf,
+    /// Module root the module was loaded from
+    pub root: ModuleRoot,
+    /// Entry for the module in `outputs.systems.modules.nixos` of the flake
+    pub flakeattr: String,
     pub config: ModuleData,
 }
 
@@ -54,6 +69,15 @@ pub enum OptionType {
     },
     Text {
         default: String,
+        /// Regular expression the whole value must match
+        #[serde(default)]
+        pattern: Option<String>,
+        #[serde(default)]
+        min_length: Option<usize>,
+        #[serde(default)]
+        max_length: Option<usize>,
+        #[serde(default)]
+        placeholder: Option<String>,
     },
     Enum {
         default: String,
@@ -62,6 +86,125 @@ pub enum OptionType {
     NumberList {
         default: Vec<u32>,
     },
+    StringList {
+        default: Vec<String>,
+    },
+    /// Any number of values chosen from `options`, written as a list of strings
+    MultiEnum {
+        default: Vec<String>,
+        options: HashMap<String, String>,
+    },
+    /// An attribute set, such as `environment.variables`
+    Map {
+        key_type: MapType,
+        value_type: MapType,
+        #[serde(default)]
+        default: BTreeMap<String, MapValue>,
+    },
+    /// A password or key, stored outside the Nix store.
+    /// The option is set to the path of a root-only file holding the value.
+    Secret {
+        #[serde(default)]
+        placeholder: Option<String>,
+    },
+    Path {
+        default: String,
+        kind: PathKind,
+        /// Reject paths that do not exist when the option is set
+        #[serde(default)]
+        must_exist: bool,
+        #[serde(default)]
+        format: PathFormat,
+    },
+    Integer {
+        default: i64,
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+        /// Values must be a multiple of `step` away from `min` (or zero)
+        #[serde(default)]
+        step: Option<i64>,
+    },
+}
+
+/// Type of the names or values of a `Map` option
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MapType {
+    String,
+    Integer,
+    Bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MapValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl MapType {
+    /// Parse a name or value typed by a user.
+    pub fn parse(&self, input: &str) -> Result<MapValue> {
+        match self {
+            MapType::String => Ok(MapValue::String(input.to_string())),
+            MapType::Integer => input
+                .trim()
+                .parse::<i64>()
+                .map(MapValue::Integer)
+                .with_context(|| format!("Invalid integer: {}", input)),
+            MapType::Bool => match input.trim() {
+                "true" => Ok(MapValue::Bool(true)),
+                "false" => Ok(MapValue::Bool(false)),
+                _ => anyhow::bail!("Invalid boolean: {}", input),
+            },
+        }
+    }
+
+    /// Read a value written by `MapValue::value`.
+    pub fn decode(&self, value: &str) -> Option<MapValue> {
+        match self {
+            MapType::String => nixstring::unquote(value).map(MapValue::String),
+            _ => self.parse(value).ok(),
+        }
+    }
+}
+
+impl MapValue {
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> String {
+        match self {
+            MapValue::Bool(value) => value.to_string(),
+            MapValue::Integer(value) => value.to_string(),
+            MapValue::String(value) => value.to_string(),
+        }
+    }
+    pub fn value(&self) -> String {
+        match self {
+            MapValue::String(value) => nixstring::quote(value),
+            value => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+    File,
+    Directory,
+}
+
+/// How a `Path` option is written to `modules.nix`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PathFormat {
+    /// A string such as `"/etc/wallpaper.png"`
+    #[default]
+    String,
+    /// A path literal such as `/etc/wallpaper.png`, copied into the Nix store
+    Path,
 }
 
 impl OptionType {
@@ -71,6 +214,224 @@ impl OptionType {
     pub fn is_text(&self) -> bool {
         matches!(self, OptionType::Text { .. })
     }
+
+    /// Value the option has when it is not set in `modules.nix`.
+    pub fn default_value(&self) -> ModuleOption {
+        match self {
+            OptionType::Switch { default } => ModuleOption::Switch { value: *default },
+            OptionType::Text { default, .. } => ModuleOption::Text {
+                value: default.to_string(),
+            },
+            OptionType::Enum { default, options } => ModuleOption::Enum {
+                value: default.to_string(),
+                pretty: options
+                    .get(default)
+                    .cloned()
+                    .unwrap_or_else(|| default.to_string()),
+            },
+            OptionType::NumberList { default } => ModuleOption::NumberList {
+                value: default.clone(),
+            },
+            OptionType::StringList { default } => ModuleOption::StringList {
+                value: default.clone(),
+            },
+            OptionType::MultiEnum { default, .. } => ModuleOption::MultiEnum {
+                value: default.clone(),
+            },
+            OptionType::Map { default, .. } => ModuleOption::Map {
+                value: default.clone(),
+            },
+            OptionType::Secret { .. } => ModuleOption::Secret {
+                path: String::new(),
+                secret: None,
+            },
+            OptionType::Path { default, format, .. } => ModuleOption::Path {
+                value: default.to_string(),
+                literal: *format == PathFormat::Path,
+            },
+            OptionType::Integer { default, .. } => ModuleOption::Integer { value: *default },
+        }
+    }
+
+    /**
+     * Parse a value typed by a user, such as `true`, `some text`, an enum key or label,
+     * a comma or space separated list of numbers, or a comma separated list of strings,
+     * enum keys or enum labels, a path, or comma separated `name=value` pairs.
+     */
+    pub fn parse_value(&self, input: &str) -> Result<ModuleOption> {
+        // Whitespace is part of text values
+        let text = input;
+        let input = input.trim();
+        match self {
+            OptionType::Switch { .. } => match input {
+                "true" | "on" | "enabled" => Ok(ModuleOption::Switch { value: true }),
+                "false" | "off" | "disabled" => Ok(ModuleOption::Switch { value: false }),
+                _ => anyhow::bail!("Invalid value for switch option: {}", input),
+            },
+            OptionType::Text { .. } => {
+                self.check_text(text)?;
+                Ok(ModuleOption::Text {
+                    value: text.to_string(),
+                })
+            }
+            OptionType::Enum { options, .. } => options
+                .iter()
+                .find(|(key, pretty)| *key == input || *pretty == input)
+                .map(|(key, pretty)| ModuleOption::Enum {
+                    value: key.to_string(),
+                    pretty: pretty.to_string(),
+                })
+                .with_context(|| format!("Invalid value for enum option: {}", input)),
+            OptionType::NumberList { .. } => {
+                let value = input
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("Invalid value for numberlist option: {}", input))?;
+                Ok(ModuleOption::NumberList { value })
+            }
+            OptionType::StringList { .. } => Ok(ModuleOption::StringList {
+                value: input
+                    .split(',')
+                    .map(|x| x.trim())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_string())
+                    .collect(),
+            }),
+            OptionType::MultiEnum { options, .. } => {
+                let value = input
+                    .split(',')
+                    .map(|x| x.trim())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| {
+                        options
+                            .iter()
+                            .find(|(key, pretty)| *key == x || *pretty == x)
+                            .map(|(key, _)| key.to_string())
+                            .with_context(|| format!("Invalid value for multienum option: {}", x))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(ModuleOption::MultiEnum { value })
+            }
+            OptionType::Map { key_type, value_type, .. } => {
+                let value = input
+                    .split(',')
+                    .filter(|x| !x.trim().is_empty())
+                    .map(|x| {
+                        let (name, value) = x
+                            .split_once('=')
+                            .with_context(|| format!("Invalid value for map option: {}", x))?;
+                        let name = key_type.parse(name.trim())?.to_string();
+                        Ok((name, value_type.parse(value.trim())?))
+                    })
+                    .collect::<Result<BTreeMap<_, _>>>()?;
+                Ok(ModuleOption::Map { value })
+            }
+            OptionType::Secret { .. } => anyhow::bail!(
+                "Secret options can not be set from text input, as it may be visible to other users"
+            ),
+            OptionType::Path { format, .. } => {
+                self.check_path(input)?;
+                Ok(ModuleOption::Path {
+                    value: input.to_string(),
+                    literal: *format == PathFormat::Path,
+                })
+            }
+            OptionType::Integer { .. } => {
+                let value = input
+                    .parse::<i64>()
+                    .with_context(|| format!("Invalid value for integer option: {}", input))?;
+                self.check_integer(value)?;
+                Ok(ModuleOption::Integer { value })
+            }
+        }
+    }
+
+    /// Check that `value` satisfies the pattern and length rules of a `Text` option.
+    /// The error describes the rule that failed.
+    pub fn check_text(&self, value: &str) -> Result<()> {
+        if let OptionType::Text {
+            pattern,
+            min_length,
+            max_length,
+            ..
+        } = self
+        {
+            let length = value.chars().count();
+            if let Some(min_length) = min_length {
+                if length < *min_length {
+                    anyhow::bail!("Must be at least {} characters long", min_length);
+                }
+            }
+            if let Some(max_length) = max_length {
+                if length > *max_length {
+                    anyhow::bail!("Must be at most {} characters long", max_length);
+                }
+            }
+            if let Some(pattern) = pattern {
+                let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                    .with_context(|| format!("Invalid pattern in module: {}", pattern))?;
+                if !regex.is_match(value) {
+                    anyhow::bail!("Must match the pattern {}", pattern);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `value` is a usable path for a `Path` option.
+    pub fn check_path(&self, value: &str) -> Result<()> {
+        if let OptionType::Path {
+            kind,
+            must_exist,
+            format,
+            ..
+        } = self
+        {
+            let path = std::path::Path::new(value);
+            if *format == PathFormat::Path && !path.is_absolute() {
+                anyhow::bail!("Must be an absolute path");
+            }
+            if *must_exist {
+                match kind {
+                    PathKind::File if !path.is_file() => {
+                        anyhow::bail!("File {} does not exist", value)
+                    }
+                    PathKind::Directory if !path.is_dir() => {
+                        anyhow::bail!("Directory {} does not exist", value)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `value` is within the bounds and step of an `Integer` option.
+    pub fn check_integer(&self, value: i64) -> Result<()> {
+        if let OptionType::Integer { min, max, step, .. } = self {
+            if let Some(min) = min {
+                if value < *min {
+                    anyhow::bail!("Value {} is less than the minimum of {}", value, min);
+                }
+            }
+            if let Some(max) = max {
+                if value > *max {
+                    anyhow::bail!("Value {} is greater than the maximum of {}", value, max);
+                }
+            }
+            if let Some(step) = step.filter(|x| *x > 0) {
+                // Widened so the distance from the minimum can not overflow
+                if (value as i128 - min.unwrap_or(0) as i128).rem_euclid(step as i128) != 0 {
+                    anyhow::bail!("Value {} is not a multiple of {}", value, step);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -82,7 +443,8 @@ pub struct ConfigDetails {
     pub default: Option<bool>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum ModuleOption {
     Switch {
         value: bool
@@ -96,6 +458,49 @@ pub enum ModuleOption {
     },
     NumberList {
         value: Vec<u32>
+    },
+    StringList {
+        value: Vec<String>
+    },
+    MultiEnum {
+        value: Vec<String>
+    },
+    Map {
+        value: BTreeMap<String, MapValue>
+    },
+    Secret {
+        /// File holding the secret, the only part written to `modules.nix`
+        path: String,
+        /// New value to store, if it is being changed
+        #[serde(skip)]
+        secret: Option<SecretValue>
+    },
+    Path {
+        value: String,
+        /// Written as a path literal rather than a string
+        literal: bool
+    },
+    Integer {
+        value: i64
+    }
+}
+
+/// A change to an option that has not been written to `modules.nix` yet
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingChange {
+    Set(ModuleOption),
+    /// Remove the option so the module default applies
+    Unset,
+}
+
+impl PendingChange {
+    /// The new value, or `None` if the option is unset.
+    pub fn value(&self) -> Option<&ModuleOption> {
+        match self {
+            PendingChange::Set(value) => Some(value),
+            PendingChange::Unset => None,
+        }
     }
 }
 
@@ -106,15 +511,26 @@ impl ModuleOption {
             ModuleOption::Switch { value } => if *value { String::from("Enabled") } else { String::from("Disabled") },
             ModuleOption::Text { value } => format!("\"{}\"", value),
             ModuleOption::Enum { pretty, .. } => pretty.to_string(),
-            ModuleOption::NumberList { value } => value.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")
+            ModuleOption::NumberList { value } => value.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "),
+            ModuleOption::StringList { value } => value.join(", "),
+            ModuleOption::MultiEnum { value } => value.join(", "),
+            ModuleOption::Map { value } => value.iter().map(|(k, v)| format!("{} = {}", k, v.to_string())).collect::<Vec<_>>().join(", "),
+            ModuleOption::Secret { .. } => String::from("••••••••"),
+            ModuleOption::Path { value, .. } => value.to_string(),
+            ModuleOption::Integer { value } => value.to_string(),
         }
     }
     pub fn value(&self) -> String {
         match self {
             ModuleOption::Switch { value } => value.to_string(),
-            ModuleOption::Text { value } => format!("\"{}\"", value),
+            ModuleOption::Text { value } => nixstring::quote(value),
             ModuleOption::Enum { value, .. } => value.to_string(),
-            ModuleOption::NumberList { value } => format!("[ {} ]", value.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "))
+            ModuleOption::NumberList { value } => format!("[ {} ]", value.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ")),
+            ModuleOption::StringList { value } | ModuleOption::MultiEnum { value } => format!("[ {} ]", value.iter().map(|x| nixstring::quote(x)).collect::<Vec<_>>().join(" ")),
+            ModuleOption::Map { value } => nixstring::attrs(value.iter().map(|(k, v)| (k.as_str(), v.value()))),
+            ModuleOption::Secret { path, .. } => nixstring::quote(path),
+            ModuleOption::Path { value, literal } => if *literal { nixstring::path(value) } else { nixstring::quote(value) },
+            ModuleOption::Integer { value } => value.to_string(),
         }
     }
 }
@@ -1,26 +1,19 @@
 use std::fs;
 
-use crate::{MODULES_CONFIG, modules::OptionType};
+use crate::modules::OptionType;
 
-use super::{Module, OptionData};
+use super::{configfile::ModuleConfigFile, nixstring, secret, Module, OptionData};
 use anyhow::{Result, Context};
 
-fn write_module_option(opt: &str, value: &str) -> Result<()> {
-    let moduleconfig = fs::read_to_string(MODULES_CONFIG)?;
-    let out = nixpkgs_fmt::reformat_string(&nix_editor::write::write(&moduleconfig, opt, value)?);
-    fs::write(MODULES_CONFIG, out)?;
-    Ok(())
-}
-
-fn deref_module_option(opt: &str) -> Result<()> {
-    let moduleconfig = fs::read_to_string(MODULES_CONFIG)?;
-    let out = nixpkgs_fmt::reformat_string(&nix_editor::write::deref(&moduleconfig, opt)?);
-    fs::write(MODULES_CONFIG, out)?;
-    Ok(())
-}
-
 impl Module {
-    pub fn setoption(&self, option: &OptionData, value: &str) -> Result<()>{
+    pub fn setoption(&self, modulefile: &ModuleConfigFile, option: &OptionData, value: &str) -> Result<()>{
+        self.checkoption(option, value)?;
+        modulefile.write_option(&option.id, value)?;
+        Ok(())
+    }
+
+    /// Check that `value`, a Nix expression, is a valid value for `option` of this module.
+    pub fn checkoption(&self, option: &OptionData, value: &str) -> Result<()> {
         let config = &self.config;
         if !config.options.iter().any(|o| o.id == option.id) {
             anyhow::bail!("Option {} not found in module {}", option.id, self.name);
@@ -35,17 +28,46 @@ impl Module {
                 }
             },
             OptionType::Text { .. } => {
-                if value.starts_with('"') && value.ends_with('"') {
-                    // Do nothing
-                } else {
-                    anyhow::bail!("Invalid value for text option: {}", value);
-                }
+                let text = nixstring::unquote(value).with_context(|| format!("Invalid value for text option: {}", value))?;
+                option.op_type.check_text(&text)?;
             },
             OptionType::Enum { options, .. } => {
-                if !options.values().any(|x| x == value) {
+                if !options.contains_key(value) {
                     anyhow::bail!("Invalid value for enum option: {}", value);
                 }
             },
+            OptionType::StringList { .. } => {
+                if nixstring::unquote_list(value).is_none() {
+                    anyhow::bail!("Invalid value for stringlist option: {}", value);
+                }
+            }
+            OptionType::MultiEnum { options, .. } => {
+                let values = nixstring::unquote_list(value).with_context(|| format!("Invalid value for multienum option: {}", value))?;
+                if let Some(invalid) = values.iter().find(|x| !options.contains_key(*x)) {
+                    anyhow::bail!("Invalid value for multienum option: {}", invalid);
+                }
+            }
+            OptionType::Map { key_type, value_type, .. } => {
+                let entries = nixstring::unquote_attrs(value).with_context(|| format!("Invalid value for map option: {}", value))?;
+                for (name, value) in entries {
+                    key_type.parse(&name)?;
+                    value_type.decode(&value).with_context(|| format!("Invalid value for {} in map option: {}", name, value))?;
+                }
+            }
+            OptionType::Secret { .. } => {
+                // Only a reference to the secret file belongs in modules.nix
+                let path = nixstring::unquote(value).with_context(|| format!("Invalid value for secret option: {}", value))?;
+                let expected = secret::secretpath(&option.id);
+                if std::path::Path::new(&path) != expected {
+                    anyhow::bail!("Secret option must point to {}", expected.to_string_lossy());
+                }
+            }
+            OptionType::Path { .. } => {
+                let path = nixstring::unquote(value)
+                    .or_else(|| nixstring::unpath(value))
+                    .with_context(|| format!("Invalid value for path option: {}", value))?;
+                option.op_type.check_path(&path)?;
+            }
             OptionType::NumberList { .. } => {
                 if value.starts_with('[') && value.ends_with(']') {
                     // Do nothing
@@ -53,35 +75,36 @@ impl Module {
                     anyhow::bail!("Invalid value for numberlist option: {}", value);
                 }
             }
+            OptionType::Integer { .. } => {
+                let number = value.parse::<i64>().with_context(|| format!("Invalid value for integer option: {}", value))?;
+                option.op_type.check_integer(number)?;
+            }
         }
-    
-        write_module_option(&option.id, value)?;
-
         Ok(())
     }
 
-    pub fn deref_option(&self, option: &OptionData) -> Result<()> {
+    pub fn deref_option(&self, modulefile: &ModuleConfigFile, option: &OptionData) -> Result<()> {
         let config = &self.config;
         if !config.options.iter().any(|o| o.id == option.id) {
             anyhow::bail!("Option {} not found in module {}", option.id, self.name);
         }
-        deref_module_option(&option.id)?;
+        modulefile.deref_option(&option.id)?;
         Ok(())
     }
     
 
-    pub fn enable(&self, enable: bool) -> Result<()> {
+    pub fn enable(&self, modulefile: &ModuleConfigFile, enable: bool) -> Result<()> {
         let config = &self.config;
         let options = &config.options;
         let enableoption = options.iter().find(|option| option.id.split('.').last() == Some("enable")).context("No enable option found")?;
-        write_module_option(&enableoption.id, &enable.to_string())?;
+        modulefile.write_option(&enableoption.id, &enable.to_string())?;
         Ok(())  
     }
 
-    pub fn remove(self) -> Result<()> {
+    pub fn remove(self, modulefile: &ModuleConfigFile) -> Result<()> {
         let config = &self.config;
         for option in &config.options {
-            self.deref_option(option)?;
+            self.deref_option(modulefile, option)?;
         }
         fs::remove_dir_all(self.path)?;
         Ok(())
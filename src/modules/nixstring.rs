@@ -0,0 +1,270 @@
+//! Encoding and decoding of Nix string literals.
+//!
+//! Values are always written as double quoted strings, which keeps them on one line and
+//! unaffected by `nixpkgs-fmt` re-indenting. Both double quoted and `''` indented strings
+//! are read back, so hand written multi-line strings in `modules.nix` are understood too.
+//! Strings containing `${ }` interpolation have no static value and are not decoded.
+
+/// Quote `value` as a double quoted Nix string, escaping characters with special meaning.
+pub fn quote(value: &str) -> String {
+    let mut output = String::from("\"");
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => output.push_str("\\$"),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// Read a double quoted or `''` indented Nix string literal, the inverse of `quote`.
+pub fn unquote(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Some(inner) = value
+        .strip_prefix("''")
+        .and_then(|x| x.strip_suffix("''"))
+    {
+        unquote_indented(inner)
+    } else {
+        unquote_double(value.strip_prefix('"')?.strip_suffix('"')?)
+    }
+}
+
+/// Read a Nix list of string literals such as `[ "a" "b" ]`.
+pub fn unquote_list(value: &str) -> Option<Vec<String>> {
+    let inner = value.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut output = vec![];
+    let mut rest = inner.trim_start();
+    while !rest.is_empty() {
+        // Find the closing quote, skipping escaped characters
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in rest.char_indices().skip(1) {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end?;
+        output.push(unquote(&rest[..=end])?);
+        rest = rest[end + 1..].trim_start();
+    }
+    Some(output)
+}
+
+/// Write an absolute path as a Nix path literal.
+/// Paths with characters a literal cannot hold are written as `/. + "/some path"`.
+pub fn path(value: &str) -> String {
+    let literal = value.starts_with('/')
+        && !value.ends_with('/')
+        && !value.contains("//")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c));
+    if literal {
+        value.to_string()
+    } else {
+        format!("/. + {}", quote(value))
+    }
+}
+
+/// Read a path written by `path`.
+pub fn unpath(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Some(string) = value.strip_prefix("/. +") {
+        unquote(string).filter(|x| x.starts_with('/'))
+    } else if value.starts_with('/')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c))
+    {
+        Some(value.to_string())
+    } else {
+        None
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+];
+
+/// Write an attribute name, quoting it unless it is a plain identifier.
+pub fn attrname(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c))
+        && !KEYWORDS.contains(&name);
+    if identifier {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+/// Write an attribute set from names and already encoded values, such as `{ FOO = "bar"; }`.
+pub fn attrs<'a>(entries: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    let entries = entries
+        .into_iter()
+        .map(|(name, value)| format!("{} = {}; ", attrname(name), value))
+        .collect::<String>();
+    format!("{{ {}}}", entries)
+}
+
+/// Read a flat attribute set such as `{ FOO = "bar"; "some name" = 1; }`.
+/// Returns the names and the still encoded values, in file order.
+pub fn unquote_attrs(value: &str) -> Option<Vec<(String, String)>> {
+    let inner = value.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut output = vec![];
+    let mut rest = inner.trim_start();
+    while !rest.is_empty() {
+        let (name, after) = if rest.starts_with('"') {
+            let end = literal_end(rest)?;
+            (unquote(&rest[..end])?, &rest[end..])
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "_'-".contains(c)))
+                .filter(|x| *x > 0)?;
+            (rest[..end].to_string(), &rest[end..])
+        };
+        let after = after.trim_start().strip_prefix('=')?.trim_start();
+        let end = literal_end(after)?;
+        output.push((name, after[..end].trim().to_string()));
+        rest = after[end..].trim_start().strip_prefix(';')?.trim_start();
+    }
+    Some(output)
+}
+
+/// Length of the string or plain literal at the start of `value`.
+/// Plain literals end at the next `;`.
+fn literal_end(value: &str) -> Option<usize> {
+    let bytes = value.as_bytes();
+    if value.starts_with("''") {
+        let mut i = 2;
+        while i + 1 < bytes.len() {
+            if bytes[i] == b'\'' && bytes[i + 1] == b'\'' {
+                match bytes.get(i + 2) {
+                    Some(b'\'') | Some(b'$') => i += 3,
+                    Some(b'\\') => i += 4,
+                    _ => return Some(i + 2),
+                }
+            } else {
+                i += 1;
+            }
+        }
+        None
+    } else if value.starts_with('"') {
+        let mut i = 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return Some(i + 1),
+                _ => i += 1,
+            }
+        }
+        None
+    } else {
+        value.find(';')
+    }
+}
+
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    }
+}
+
+fn unquote_double(inner: &str) -> Option<String> {
+    let mut output = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => output.push(unescape(chars.next()?)),
+            // An unescaped quote would have ended the string
+            '"' => return None,
+            '$' => match chars.peek() {
+                Some('{') => return None,
+                // `$${` is a literal `$${`
+                Some('$') => {
+                    chars.next();
+                    output.push_str("$$");
+                }
+                _ => output.push('$'),
+            },
+            c => output.push(c),
+        }
+    }
+    Some(output)
+}
+
+fn unquote_indented(inner: &str) -> Option<String> {
+    let mut lines = inner.split('\n').collect::<Vec<_>>();
+    let blank = |line: &str| line.chars().all(|c| c == ' ');
+    // A first line containing only spaces is not part of the string
+    if lines.len() > 1 && blank(lines[0]) {
+        lines.remove(0);
+    }
+    // The smallest indentation of non-blank lines is removed from every line
+    let indent = lines
+        .iter()
+        .filter(|line| !blank(line))
+        .map(|line| line.len() - line.trim_start_matches(' ').len())
+        .min()
+        .unwrap_or(0);
+    let last = lines.len() - 1;
+    let stripped = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if blank(line) && i == last {
+                ""
+            } else {
+                line.get(indent..).unwrap_or("")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut output = String::new();
+    let mut chars = stripped.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                match chars.next()? {
+                    '\'' => output.push_str("''"),
+                    '$' => output.push('$'),
+                    '\\' => output.push(unescape(chars.next()?)),
+                    // A bare `''` would have ended the string
+                    _ => return None,
+                }
+            }
+            '$' => match chars.peek() {
+                Some('{') => return None,
+                Some('$') => {
+                    chars.next();
+                    output.push_str("$$");
+                }
+                _ => output.push('$'),
+            },
+            c => output.push(c),
+        }
+    }
+    Some(output)
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A step of a rebuild reported by `smm-helper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Writing,
+    Evaluating,
+    Building,
+    Downloading,
+    Activating,
+    Cleaning,
+}
+
+impl Phase {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "writing" => Some(Phase::Writing),
+            "evaluating" => Some(Phase::Evaluating),
+            "building" => Some(Phase::Building),
+            "downloading" => Some(Phase::Downloading),
+            "activating" => Some(Phase::Activating),
+            "cleaning" => Some(Phase::Cleaning),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Phase::Writing => "Writing configuration",
+            Phase::Evaluating => "Evaluating configuration",
+            Phase::Building => "Building",
+            Phase::Downloading => "Downloading",
+            Phase::Activating => "Activating",
+            Phase::Cleaning => "Removing old generations",
+        }
+    }
+}
+
+/// An event `smm-helper` writes to the file passed with `--progress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A phase started or advanced, with the number of finished and total steps if it has any
+    Phase { phase: Phase, steps: Option<(u32, u32)> },
+    Error(String),
+    Done,
+}
+
+impl ProgressEvent {
+    /// Parse a line of tab separated fields, ignoring events this version does not know.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        match fields.next()? {
+            "error" => Some(ProgressEvent::Error(fields.next().unwrap_or_default().to_string())),
+            "done" => Some(ProgressEvent::Done),
+            name => {
+                let phase = Phase::parse(name)?;
+                let steps = match (fields.next(), fields.next()) {
+                    (Some(done), Some(total)) => Some((done.parse().ok()?, total.parse().ok()?)),
+                    _ => None,
+                };
+                Some(ProgressEvent::Phase { phase, steps })
+            }
+        }
+    }
+}
+
+/// A file `smm-helper` appends progress events to, read as it grows.
+/// The file is removed when this is dropped.
+#[derive(Debug)]
+pub struct ProgressFile {
+    path: PathBuf,
+    file: File,
+    /// Incomplete last line read so far
+    partial: String,
+}
+
+impl ProgressFile {
+    pub fn new() -> Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::var_os("XDG_RUNTIME_DIR")
+            .filter(|x| !x.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        let path = dir.join(format!(
+            "smm-progress-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.to_string_lossy()))?;
+        Ok(Self {
+            path,
+            file,
+            partial: String::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Events written since the last read.
+    pub fn read(&mut self) -> Vec<ProgressEvent> {
+        let mut data = String::new();
+        if self.file.read_to_string(&mut data).is_err() {
+            // Skip whatever could not be decoded, events are written as whole lines
+            let _ = self.file.seek(SeekFrom::End(0));
+            self.partial.clear();
+            return vec![];
+        }
+        self.partial.push_str(&data);
+        let complete = match self.partial.rfind('\n') {
+            Some(end) => self.partial.drain(..=end).collect::<String>(),
+            None => return vec![],
+        };
+        complete.lines().filter_map(ProgressEvent::parse).collect()
+    }
+}
+
+impl Drop for ProgressFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Directory that SnowflakeOS installs system modules into.
+pub const SYSTEM_MODULES_DIR: &str = "/etc/snowflakeos-modules";
+/// Name of the module directory inside XDG data directories.
+pub const MODULES_DIR_NAME: &str = "snowflakeos-modules";
+/// Colon separated list of extra module roots, searched before all others.
+pub const MODULES_PATH_ENV: &str = "SNOWFLAKEOS_MODULES_PATH";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleRootKind {
+    /// Passed on the command line or through `SNOWFLAKEOS_MODULES_PATH`
+    Override,
+    /// `$XDG_DATA_HOME/snowflakeos-modules`
+    User,
+    /// `$XDG_DATA_DIRS/snowflakeos-modules`
+    Data,
+    /// `/etc/snowflakeos-modules`
+    System,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ModuleRoot {
+    pub path: PathBuf,
+    pub kind: ModuleRootKind,
+}
+
+impl ModuleRoot {
+    pub fn new(path: impl Into<PathBuf>, kind: ModuleRootKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+        }
+    }
+}
+
+impl std::fmt::Display for ModuleRoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            ModuleRootKind::Override => "override",
+            ModuleRootKind::User => "user",
+            ModuleRootKind::Data => "data",
+            ModuleRootKind::System => "system",
+        };
+        write!(f, "{} ({})", self.path.to_string_lossy(), kind)
+    }
+}
+
+/**
+ * Get the ordered list of directories to search for modules.
+ *
+ * Roots are returned highest precedence first: `overrides` (from the command line),
+ * `SNOWFLAKEOS_MODULES_PATH`, the user data directory, `$XDG_DATA_DIRS` and finally
+ * `/etc/snowflakeos-modules`. When the same module id exists in more than one root,
+ * the first root wins.
+ */
+pub fn moduleroots(overrides: &[PathBuf]) -> Vec<ModuleRoot> {
+    let mut roots = overrides
+        .iter()
+        .map(|path| ModuleRoot::new(path, ModuleRootKind::Override))
+        .collect::<Vec<_>>();
+
+    if let Some(paths) = env::var_os(MODULES_PATH_ENV) {
+        roots.extend(
+            env::split_paths(&paths)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| ModuleRoot::new(path, ModuleRootKind::Override)),
+        );
+    }
+
+    let datahome = env::var_os("XDG_DATA_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    if let Some(datahome) = datahome {
+        roots.push(ModuleRoot::new(
+            datahome.join(MODULES_DIR_NAME),
+            ModuleRootKind::User,
+        ));
+    }
+
+    let datadirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    roots.extend(
+        env::split_paths(&datadirs)
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| ModuleRoot::new(path.join(MODULES_DIR_NAME), ModuleRootKind::Data)),
+    );
+
+    roots.push(ModuleRoot::new(SYSTEM_MODULES_DIR, ModuleRootKind::System));
+
+    let mut unique: Vec<ModuleRoot> = Vec::new();
+    for root in roots {
+        if !unique.iter().any(|x| x.path == root.path) {
+            unique.push(root);
+        }
+    }
+    unique
+}
@@ -0,0 +1,88 @@
+//! Values of `Secret` options.
+//!
+//! A secret is never written to `modules.nix`, which ends up in the world-readable Nix store.
+//! `smm-helper` stores it in a root-only file under `SECRETS_DIR` and `modules.nix` only holds
+//! the path to that file. The secret reaches the helper through a temporary file readable only
+//! by the user, so it never appears in the arguments of a process.
+
+use std::{
+    env,
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{Context, Result};
+
+/// Directory `smm-helper` stores secrets in, readable only by root
+pub const SECRETS_DIR: &str = "/var/lib/snowflakeos-module-manager/secrets";
+
+/// Path of the file holding the secret for option `id`.
+pub fn secretpath(id: &str) -> PathBuf {
+    Path::new(SECRETS_DIR).join(id)
+}
+
+/// A secret entered by the user. `Debug` never shows the value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretValue(String);
+
+impl SecretValue {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretValue(<redacted>)")
+    }
+}
+
+/// A secret written to a temporary file for `smm-helper` to read.
+/// The file is removed when this is dropped.
+#[derive(Debug)]
+pub struct StagedSecret {
+    path: PathBuf,
+}
+
+impl StagedSecret {
+    pub fn new(secret: &SecretValue) -> Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::var_os("XDG_RUNTIME_DIR")
+            .filter(|x| !x.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        let path = dir.join(format!(
+            "smm-secret-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.to_string_lossy()))?;
+        let staged = Self { path };
+        file.write_all(secret.expose().as_bytes())?;
+        Ok(staged)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StagedSecret {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{load::LoadDiagnostic, roots::ModuleRoot, Module, ModuleData, ModuleOption};
+
+/// Version of the JSON snapshot format.
+/// Bump when a field is removed or changes meaning; adding fields is backwards compatible.
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+
+/**
+ * Serializable view of all loaded modules, their option values and pending modifications.
+ *
+ * Option values are tagged with their type, for example
+ * `{ "type": "switch", "value": true }` or `{ "type": "enum", "value": "\"gnome\"", "pretty": "GNOME" }`.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Snapshot {
+    pub schema_version: u32,
+    pub modules: Vec<ModuleSnapshot>,
+    pub pending: Vec<PendingModification>,
+    pub diagnostics: Vec<LoadDiagnostic>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModuleSnapshot {
+    pub name: String,
+    pub path: PathBuf,
+    pub root: ModuleRoot,
+    pub flakeattr: String,
+    /// Whether the module is listed in the flake module list
+    pub installed: bool,
+    pub data: ModuleData,
+    pub options: Vec<OptionSnapshot>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OptionSnapshot {
+    pub id: String,
+    /// Effective value, either set in `modules.nix` or the module default
+    pub value: ModuleOption,
+    /// Whether the value is set in `modules.nix`
+    pub explicit: bool,
+}
+
+/// A change that has not been applied yet. `new` is `None` when the option will be unset.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PendingModification {
+    pub id: String,
+    pub old: Option<ModuleOption>,
+    pub new: Option<ModuleOption>,
+}
+
+impl ModuleSnapshot {
+    pub fn new(module: &Module, installed: bool, current: &HashMap<String, ModuleOption>) -> Self {
+        Self {
+            name: module.name.to_string(),
+            path: module.path.clone(),
+            root: module.root.clone(),
+            flakeattr: module.flakeattr.to_string(),
+            installed,
+            data: module.config.clone(),
+            options: module
+                .config
+                .options
+                .iter()
+                .map(|option| OptionSnapshot {
+                    id: option.id.to_string(),
+                    value: current
+                        .get(&option.id)
+                        .cloned()
+                        .unwrap_or_else(|| option.op_type.default_value()),
+                    explicit: current.contains_key(&option.id),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new(
+        modules: &[Module],
+        available: &[Module],
+        current: &HashMap<String, ModuleOption>,
+        pending: Vec<PendingModification>,
+        diagnostics: &[LoadDiagnostic],
+    ) -> Self {
+        Self {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            modules: modules
+                .iter()
+                .map(|module| ModuleSnapshot::new(module, true, current))
+                .chain(
+                    available
+                        .iter()
+                        .map(|module| ModuleSnapshot::new(module, false, current)),
+                )
+                .collect(),
+            pending,
+            diagnostics: diagnostics.to_vec(),
+        }
+    }
+}
+
+/// Compare two sets of set options and list the differences, sorted by option id.
+pub fn pendingmodifications(
+    current: &HashMap<String, ModuleOption>,
+    modified: &HashMap<String, ModuleOption>,
+) -> Vec<PendingModification> {
+    let mut pending = current
+        .keys()
+        .chain(modified.keys())
+        .filter(|id| current.get(*id) != modified.get(*id))
+        .map(|id| PendingModification {
+            id: id.to_string(),
+            old: current.get(id).cloned(),
+            new: modified.get(id).cloned(),
+        })
+        .collect::<Vec<_>>();
+    pending.sort_by(|a, b| a.id.cmp(&b.id));
+    pending.dedup_by(|a, b| a.id == b.id);
+    pending
+}
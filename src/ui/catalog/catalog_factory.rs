@@ -0,0 +1,112 @@
+use super::page::CatalogPageInput;
+use crate::modules::Module;
+use adw::prelude::{ActionRowExt, ButtonExt, PreferencesRowExt, WidgetExt};
+use relm4::{
+    factory::FactoryView,
+    gtk,
+    prelude::{DynamicIndex, FactoryComponent},
+    FactorySender,
+};
+
+#[tracker::track]
+pub struct CatalogModuleModel {
+    #[tracker::no_eq]
+    module: Module,
+    /// Whether the module is currently in the flake module list
+    installed: bool,
+    /// Whether the module will be in the flake module list after applying changes
+    wanted: bool,
+}
+
+#[derive(Debug)]
+pub enum CatalogModuleInput {
+    Toggle,
+}
+
+#[derive(Debug)]
+pub enum CatalogModuleOutput {
+    SetInstalled(String, bool),
+}
+
+pub struct CatalogModuleInit {
+    pub module: Module,
+    pub installed: bool,
+    pub wanted: bool,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for CatalogModuleModel {
+    type ParentWidget = adw::PreferencesGroup;
+    type ParentInput = CatalogPageInput;
+    type Input = CatalogModuleInput;
+    type Output = CatalogModuleOutput;
+    type Init = CatalogModuleInit;
+    type CommandOutput = ();
+
+    view! {
+        #[root]
+        adw::ActionRow {
+            set_title: &self.module.config.name,
+            set_subtitle: self.module.config.description.as_deref().unwrap_or_default(),
+            add_suffix = &gtk::Label {
+                add_css_class: "dim-label",
+                #[track(self.changed(CatalogModuleModel::wanted()))]
+                set_visible: self.wanted != self.installed,
+                #[track(self.changed(CatalogModuleModel::wanted()))]
+                set_label: if self.wanted { "Will be added" } else { "Will be removed" },
+            },
+            add_suffix = &gtk::Button {
+                set_valign: gtk::Align::Center,
+                #[track(self.changed(CatalogModuleModel::wanted()))]
+                set_label: if self.wanted { "Remove" } else { "Add" },
+                #[track(self.changed(CatalogModuleModel::wanted()))]
+                set_css_classes: if self.wanted { &["destructive-action"] } else { &["suggested-action"] },
+                connect_clicked[sender] => move |_| {
+                    sender.input(CatalogModuleInput::Toggle);
+                }
+            }
+        }
+    }
+
+    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        Self {
+            module: init.module,
+            installed: init.installed,
+            wanted: init.wanted,
+            tracker: 0,
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &DynamicIndex,
+        root: &Self::Root,
+        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        sender: FactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+        widgets
+    }
+
+    fn update(&mut self, message: Self::Input, sender: FactorySender<Self>) {
+        self.reset();
+        match message {
+            CatalogModuleInput::Toggle => {
+                self.set_wanted(!self.wanted);
+                sender.output(CatalogModuleOutput::SetInstalled(
+                    self.module.flakeattr.to_string(),
+                    self.wanted,
+                ));
+            }
+        }
+    }
+
+    fn forward_to_parent(output: Self::Output) -> Option<Self::ParentInput> {
+        let output = match output {
+            CatalogModuleOutput::SetInstalled(flakeattr, install) => {
+                CatalogPageInput::SetInstalled(flakeattr, install)
+            }
+        };
+        Some(output)
+    }
+}
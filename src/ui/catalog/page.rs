@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use adw::prelude::{ButtonExt, OrientableExt, PreferencesGroupExt, WidgetExt};
+use log::error;
+use relm4::{
+    factory::FactoryVecDeque, gtk, ComponentParts, ComponentSender, RelmWidgetExt,
+    SimpleComponent,
+};
+
+use crate::{modules::Module, ui::window::AppInput};
+
+use super::catalog_factory::{CatalogModuleInit, CatalogModuleModel};
+
+#[tracker::track]
+pub struct CatalogPageModel {
+    #[tracker::no_eq]
+    installedfactory: FactoryVecDeque<CatalogModuleModel>,
+    #[tracker::no_eq]
+    availablefactory: FactoryVecDeque<CatalogModuleModel>,
+    show_apply: bool,
+}
+
+#[derive(Debug)]
+pub enum CatalogPageInput {
+    /// Installed modules, available modules and pending flake module changes
+    OpenCatalogPage(Vec<Module>, Vec<Module>, HashMap<String, bool>),
+    SetInstalled(String, bool),
+    ShowApply(bool),
+}
+
+pub struct CatalogPageInit {}
+
+#[relm4::component(pub)]
+impl SimpleComponent for CatalogPageModel {
+    type Input = CatalogPageInput;
+    type Output = AppInput;
+    type Init = CatalogPageInit;
+
+    view! {
+        #[root]
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_hexpand: true,
+            set_vexpand: true,
+            adw::HeaderBar {
+                #[wrap(Some)]
+                set_title_widget = &gtk::Label {
+                    set_label: "Module Catalog",
+                },
+                pack_start = &gtk::Button {
+                    add_css_class: "flat",
+                    set_icon_name: "go-previous-symbolic",
+                    connect_clicked[sender] => move |_| {
+                        let _ = sender.output(AppInput::CloseCatalogPage);
+                    },
+                },
+                pack_end = &gtk::Button {
+                    #[track(model.changed(CatalogPageModel::show_apply()))]
+                    set_visible: model.show_apply,
+                    add_css_class: "suggested-action",
+                    set_label: "Apply",
+                    connect_clicked[sender] => move |_| {
+                        if sender.output(AppInput::ApplyChanges).is_err() { error!("Error sender AppInput::Applychanges") }
+                    }
+                },
+                pack_end = &gtk::Button {
+                    #[track(model.changed(CatalogPageModel::show_apply()))]
+                    set_visible: model.show_apply,
+                    set_label: "Discard",
+                    set_tooltip_text: Some("Discard changes"),
+                    connect_clicked[sender] => move |_| {
+                        if sender.output(AppInput::DiscardChanges).is_err() { error!("Error sending AppInput::DiscardChanges") }
+                    }
+                }
+            },
+            gtk::ScrolledWindow {
+                set_vexpand: true,
+                adw::Clamp {
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 15,
+                        set_margin_all: 15,
+                        #[local_ref]
+                        installedgroup -> adw::PreferencesGroup {
+                            set_title: "Installed",
+                            #[watch]
+                            set_visible: !model.installedfactory.is_empty(),
+                        },
+                        #[local_ref]
+                        availablegroup -> adw::PreferencesGroup {
+                            set_title: "Available",
+                            #[watch]
+                            set_visible: !model.availablefactory.is_empty(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        _init: Self::Init,
+        root: &Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let installedfactory =
+            FactoryVecDeque::new(adw::PreferencesGroup::new(), sender.input_sender());
+        let availablefactory =
+            FactoryVecDeque::new(adw::PreferencesGroup::new(), sender.input_sender());
+        let model = CatalogPageModel {
+            installedfactory,
+            availablefactory,
+            show_apply: false,
+            tracker: 0,
+        };
+        let installedgroup = model.installedfactory.widget();
+        let availablegroup = model.availablefactory.widget();
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
+        self.reset();
+        match message {
+            CatalogPageInput::OpenCatalogPage(installed, available, modified_modules) => {
+                let mut installedfactory_guard = self.installedfactory.guard();
+                installedfactory_guard.clear();
+                for module in installed {
+                    let wanted = modified_modules.get(&module.flakeattr).copied().unwrap_or(true);
+                    installedfactory_guard.push_back(CatalogModuleInit {
+                        module,
+                        installed: true,
+                        wanted,
+                    });
+                }
+                installedfactory_guard.drop();
+                let mut availablefactory_guard = self.availablefactory.guard();
+                availablefactory_guard.clear();
+                for module in available {
+                    let wanted = modified_modules.get(&module.flakeattr).copied().unwrap_or(false);
+                    availablefactory_guard.push_back(CatalogModuleInit {
+                        module,
+                        installed: false,
+                        wanted,
+                    });
+                }
+                availablefactory_guard.drop();
+            }
+            CatalogPageInput::SetInstalled(flakeattr, install) => {
+                if sender.output(AppInput::SetModuleInstalled(flakeattr, install)).is_err() { error!("Error sending: AppInput::SetModuleInstalled") }
+            }
+            CatalogPageInput::ShowApply(show) => {
+                self.set_show_apply(show)
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::window::AppInput;
+use crate::modules::load::LoadDiagnostic;
+use adw::prelude::{ActionRowExt, PreferencesRowExt, WidgetExt};
+use relm4::{
+    factory::FactoryView,
+    gtk,
+    prelude::{DynamicIndex, FactoryComponent},
+    FactorySender,
+};
+
+pub struct DiagnosticModel {
+    diagnostic: LoadDiagnostic,
+}
+
+#[derive(Debug)]
+pub enum DiagnosticInput {}
+
+#[derive(Debug)]
+pub enum DiagnosticOutput {}
+
+pub struct DiagnosticInit {
+    pub diagnostic: LoadDiagnostic,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for DiagnosticModel {
+    type ParentWidget = adw::PreferencesGroup;
+    type ParentInput = AppInput;
+    type Input = DiagnosticInput;
+    type Output = DiagnosticOutput;
+    type Init = DiagnosticInit;
+    type CommandOutput = ();
+
+    view! {
+        #[root]
+        adw::ActionRow {
+            set_title: &match (self.diagnostic.line, self.diagnostic.column) {
+                (Some(line), Some(column)) => format!("{}:{}:{}", self.diagnostic.file.to_string_lossy(), line, column),
+                (Some(line), None) => format!("{}:{}", self.diagnostic.file.to_string_lossy(), line),
+                _ => self.diagnostic.file.to_string_lossy().to_string(),
+            },
+            set_subtitle: &self.diagnostic.message,
+            set_subtitle_selectable: true,
+            add_prefix = &gtk::Image {
+                add_css_class: "error",
+                set_icon_name: Some("dialog-error-symbolic"),
+            }
+        }
+    }
+
+    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        Self {
+            diagnostic: init.diagnostic,
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &DynamicIndex,
+        root: &Self::Root,
+        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        _sender: FactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+        widgets
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: FactorySender<Self>) {
+        match message {}
+    }
+}
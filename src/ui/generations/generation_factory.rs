@@ -0,0 +1,115 @@
+use super::page::GenerationsPageInput;
+use crate::modules::generations::Generation;
+use adw::{
+    glib,
+    prelude::{ActionRowExt, ButtonExt, PreferencesRowExt, WidgetExt},
+};
+use relm4::{
+    factory::FactoryView,
+    gtk,
+    prelude::{DynamicIndex, FactoryComponent},
+    FactorySender,
+};
+
+pub struct GenerationModel {
+    generation: Generation,
+    /// Whether the module settings of the generation were saved
+    saved: bool,
+}
+
+#[derive(Debug)]
+pub enum GenerationInput {
+    Restore,
+}
+
+#[derive(Debug)]
+pub enum GenerationOutput {
+    Restore(u32),
+}
+
+pub struct GenerationInit {
+    pub generation: Generation,
+    pub saved: bool,
+}
+
+/// Creation date and NixOS version of a generation.
+fn subtitle(generation: &Generation) -> String {
+    let date = glib::DateTime::from_unix_local(generation.date)
+        .and_then(|x| x.format("%Y-%m-%d %H:%M"))
+        .map(|x| x.to_string())
+        .unwrap_or_default();
+    if generation.version.is_empty() {
+        date
+    } else {
+        format!("{} · NixOS {}", date, generation.version)
+    }
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for GenerationModel {
+    type ParentWidget = adw::PreferencesGroup;
+    type ParentInput = GenerationsPageInput;
+    type Input = GenerationInput;
+    type Output = GenerationOutput;
+    type Init = GenerationInit;
+    type CommandOutput = ();
+
+    view! {
+        #[root]
+        adw::ActionRow {
+            set_title: &format!("Generation {}", self.generation.number),
+            set_subtitle: &subtitle(&self.generation),
+            add_suffix = &gtk::Label {
+                add_css_class: "dim-label",
+                set_visible: self.generation.current,
+                set_label: "Current",
+            },
+            add_suffix = &gtk::Button {
+                set_valign: gtk::Align::Center,
+                set_label: "Restore",
+                set_sensitive: self.saved && !self.generation.current,
+                set_tooltip_text: Some(if self.saved {
+                    "Restore the module settings of this generation"
+                } else {
+                    "The module settings of this generation were not saved"
+                }),
+                connect_clicked[sender] => move |_| {
+                    sender.input(GenerationInput::Restore);
+                }
+            }
+        }
+    }
+
+    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        Self {
+            generation: init.generation,
+            saved: init.saved,
+        }
+    }
+
+    fn init_widgets(
+        &mut self,
+        _index: &DynamicIndex,
+        root: &Self::Root,
+        _returned_widget: &<Self::ParentWidget as FactoryView>::ReturnedWidget,
+        sender: FactorySender<Self>,
+    ) -> Self::Widgets {
+        let widgets = view_output!();
+        widgets
+    }
+
+    fn update(&mut self, message: Self::Input, sender: FactorySender<Self>) {
+        match message {
+            GenerationInput::Restore => {
+                sender.output(GenerationOutput::Restore(self.generation.number));
+            }
+        }
+    }
+
+    fn forward_to_parent(output: Self::Output) -> Option<Self::ParentInput> {
+        let output = match output {
+            GenerationOutput::Restore(number) => GenerationsPageInput::Restore(number),
+        };
+        Some(output)
+    }
+}
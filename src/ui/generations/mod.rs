@@ -0,0 +1,2 @@
+pub mod page;
+mod generation_factory;
@@ -0,0 +1,282 @@
+use std::process::Command;
+
+use adw::prelude::{
+    ButtonExt, ComboRowExt, ListModelExt, OrientableExt, PreferencesGroupExt, WidgetExt,
+};
+use anyhow::{anyhow, Result};
+use log::error;
+use relm4::{
+    factory::FactoryVecDeque, gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt,
+};
+
+use crate::{
+    config::LIBEXECDIR,
+    modules::{
+        configfile::textdiff,
+        generations::{generationconfig, generationpath, parsegenerations, Generation},
+    },
+    ui::window::AppInput,
+};
+
+use super::generation_factory::{GenerationInit, GenerationModel};
+
+/// List the system generations using `smm-helper`, newest first.
+pub fn listgenerations() -> Result<Vec<Generation>> {
+    let output = Command::new(format!("{}/smm-helper", LIBEXECDIR))
+        .arg("list-generations")
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parsegenerations(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[tracker::track]
+pub struct GenerationsPageModel {
+    #[tracker::no_eq]
+    generationfactory: FactoryVecDeque<GenerationModel>,
+    #[tracker::no_eq]
+    generations: Vec<Generation>,
+    #[tracker::no_eq]
+    generationlist: gtk::StringList,
+    #[tracker::no_eq]
+    fromrow: adw::ComboRow,
+    #[tracker::no_eq]
+    torow: adw::ComboRow,
+    /// Changes to `modules.nix` between the compared generations
+    diff: String,
+    /// Generations are being listed in the background
+    loading: bool,
+    /// Why the generations could not be listed
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum GenerationsPageInput {
+    OpenGenerationsPage,
+    Compare,
+    Restore(u32),
+}
+
+pub struct GenerationsPageInit {}
+
+#[relm4::component(pub)]
+impl Component for GenerationsPageModel {
+    type Input = GenerationsPageInput;
+    type Output = AppInput;
+    type Init = GenerationsPageInit;
+    /// Generations newest first, with whether their module settings were saved
+    type CommandOutput = Result<Vec<(Generation, bool)>>;
+
+    view! {
+        #[root]
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_hexpand: true,
+            set_vexpand: true,
+            adw::HeaderBar {
+                #[wrap(Some)]
+                set_title_widget = &gtk::Label {
+                    set_label: "Generations",
+                },
+                pack_start = &gtk::Button {
+                    add_css_class: "flat",
+                    set_icon_name: "go-previous-symbolic",
+                    connect_clicked[sender] => move |_| {
+                        let _ = sender.output(AppInput::CloseGenerationsPage);
+                    },
+                },
+            },
+            gtk::ScrolledWindow {
+                set_vexpand: true,
+                adw::Clamp {
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 15,
+                        set_margin_all: 15,
+                        gtk::Spinner {
+                            set_spinning: true,
+                            set_height_request: 60,
+                            #[watch]
+                            set_visible: model.loading,
+                        },
+                        adw::StatusPage {
+                            set_icon_name: Some("dialog-error-symbolic"),
+                            set_title: "Failed to list system generations",
+                            #[watch]
+                            set_description: model.error.as_deref(),
+                            #[watch]
+                            set_visible: model.error.is_some(),
+                        },
+                        adw::PreferencesGroup {
+                            set_title: "Compare",
+                            set_description: Some("Differences in module settings between two generations"),
+                            #[watch]
+                            set_visible: model.generations.len() > 1,
+                            #[local_ref]
+                            fromrow -> adw::ComboRow {
+                                set_title: "From",
+                                set_model: Some(&model.generationlist),
+                                connect_selected_notify[sender] => move |_| {
+                                    sender.input(GenerationsPageInput::Compare);
+                                }
+                            },
+                            #[local_ref]
+                            torow -> adw::ComboRow {
+                                set_title: "To",
+                                set_model: Some(&model.generationlist),
+                                connect_selected_notify[sender] => move |_| {
+                                    sender.input(GenerationsPageInput::Compare);
+                                }
+                            },
+                        },
+                        gtk::Frame {
+                            #[watch]
+                            set_visible: model.generations.len() > 1,
+                            gtk::ScrolledWindow {
+                                set_vscrollbar_policy: gtk::PolicyType::Never,
+                                set_propagate_natural_height: true,
+                                gtk::Label {
+                                    add_css_class: "monospace",
+                                    set_margin_all: 10,
+                                    set_halign: gtk::Align::Start,
+                                    set_valign: gtk::Align::Start,
+                                    set_xalign: 0.0,
+                                    set_selectable: true,
+                                    #[track(model.changed(GenerationsPageModel::diff()))]
+                                    set_label: &model.diff,
+                                }
+                            }
+                        },
+                        #[local_ref]
+                        generationgroup -> adw::PreferencesGroup {
+                            set_title: "Generations",
+                            #[watch]
+                            set_visible: !model.loading && model.error.is_none(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        _init: Self::Init,
+        root: &Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let generationfactory =
+            FactoryVecDeque::new(adw::PreferencesGroup::new(), sender.input_sender());
+        let model = GenerationsPageModel {
+            generationfactory,
+            generations: vec![],
+            generationlist: gtk::StringList::new(&[]),
+            fromrow: adw::ComboRow::new(),
+            torow: adw::ComboRow::new(),
+            diff: String::new(),
+            loading: false,
+            error: None,
+            tracker: 0,
+        };
+        let generationgroup = model.generationfactory.widget();
+        let fromrow = &model.fromrow;
+        let torow = &model.torow;
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        self.reset();
+        match message {
+            GenerationsPageInput::OpenGenerationsPage => {
+                self.set_loading(true);
+                self.set_error(None);
+                self.generations.clear();
+                self.generationfactory.guard().clear();
+                // Listing runs the helper and reads the saved settings, which can take a while
+                sender.spawn_oneshot_command(|| {
+                    Ok(listgenerations()?
+                        .into_iter()
+                        .map(|x| {
+                            let saved = generationpath(x.number).exists();
+                            (x, saved)
+                        })
+                        .collect())
+                });
+            }
+            GenerationsPageInput::Compare => {
+                let from = self.generations.get(self.fromrow.selected() as usize);
+                let to = self.generations.get(self.torow.selected() as usize);
+                if let (Some(from), Some(to)) = (from, to) {
+                    let diff = match (generationconfig(from.number), generationconfig(to.number)) {
+                        (Some(old), Some(new)) if old == new => {
+                            String::from("The module settings are the same.")
+                        }
+                        (Some(old), Some(new)) => textdiff(
+                            &old,
+                            &new,
+                            &format!("generation {}", from.number),
+                            &format!("generation {}", to.number),
+                        ),
+                        (None, _) => format!("The module settings of generation {} were not saved.", from.number),
+                        (_, None) => format!("The module settings of generation {} were not saved.", to.number),
+                    };
+                    self.set_diff(diff);
+                }
+            }
+            GenerationsPageInput::Restore(number) => {
+                if sender.output(AppInput::RestoreGeneration(number)).is_err() { error!("Error sending: AppInput::RestoreGeneration") }
+            }
+        }
+    }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        self.reset();
+        self.set_loading(false);
+        let generations = match message {
+            Ok(generations) => generations,
+            Err(e) => {
+                self.set_error(Some(e.to_string()));
+                return;
+            }
+        };
+        let mut generationfactory_guard = self.generationfactory.guard();
+        generationfactory_guard.clear();
+        for (generation, saved) in &generations {
+            generationfactory_guard.push_back(GenerationInit {
+                generation: generation.clone(),
+                saved: *saved,
+            });
+        }
+        generationfactory_guard.drop();
+        let generations = generations.into_iter().map(|(x, _)| x).collect::<Vec<_>>();
+        let labels = generations
+            .iter()
+            .map(|x| {
+                if x.current {
+                    format!("Generation {} (current)", x.number)
+                } else {
+                    format!("Generation {}", x.number)
+                }
+            })
+            .collect::<Vec<_>>();
+        self.generations = generations;
+        self.generationlist.splice(
+            0,
+            self.generationlist.n_items(),
+            &labels.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+        );
+        // Generations are listed newest first, so compare the previous one with the newest
+        self.fromrow.set_selected(1);
+        self.torow.set_selected(0);
+        sender.input(GenerationsPageInput::Compare);
+    }
+}
@@ -0,0 +1,70 @@
+/// Number of steps kept by default, older ones are forgotten
+const DEFAULT_LIMIT: usize = 100;
+
+/// Undo and redo history of a value, such as the pending changes of the application.
+#[derive(Debug)]
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    /// Most steps that can be undone
+    limit: usize,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMIT)
+    }
+}
+
+impl<T> History<T> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            limit,
+        }
+    }
+
+    /// Record `state` before it is changed. Anything that could be redone is discarded.
+    pub fn record(&mut self, state: T) {
+        self.push_undo(state);
+        self.redo.clear();
+    }
+
+    /// Step back from `current`, returning the state to restore.
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let state = self.undo.pop()?;
+        self.redo.push(current);
+        Some(state)
+    }
+
+    /// Step forward from `current`, returning the state to restore.
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let state = self.redo.pop()?;
+        self.push_undo(current);
+        Some(state)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    fn push_undo(&mut self, state: T) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.undo.len() == self.limit {
+            self.undo.remove(0);
+        }
+        self.undo.push(state);
+    }
+}
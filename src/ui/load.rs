@@ -1,6 +1,8 @@
 use crate::modules::{
     self,
-    load::{getcurrentoptions, getmodulepath, loadmoduleconfig},
+    configfile::ModuleConfigFile,
+    load::{getcurrentoptions, LoadDiagnostic, LoadedModules},
+    roots::ModuleRoot,
     ModuleOption, Module,
 };
 use anyhow::Result;
@@ -10,30 +12,40 @@ use std::{collections::HashMap, path::PathBuf};
 pub struct LoadOutput {
     pub config: nix_data::config::configfile::NixDataConfig,
     pub moduleconfig: String,
-    pub modulepath: PathBuf,
+    pub modulefile: ModuleConfigFile,
     pub flakepath: PathBuf,
     pub modules: Vec<modules::Module>,
+    pub available: Vec<modules::Module>,
+    pub moduleroots: Vec<ModuleRoot>,
+    pub diagnostics: Vec<LoadDiagnostic>,
     pub current_config: HashMap<String, ModuleOption>,
 }
 
-pub fn load() -> Result<LoadOutput> {
+pub fn load(moduleroots: Vec<ModuleRoot>) -> Result<LoadOutput> {
     let config = nix_data::config::configfile::getconfig().expect("Failed to load config");
-    let moduleconfig = loadmoduleconfig(&config).expect("Failed to load module config");
-    let modulepath = getmodulepath(&config).expect("Failed to get module path");
+    let modulefile = ModuleConfigFile::from_config(&config).expect("Failed to get module path");
+    let moduleconfig = modulefile.read().expect("Failed to load module config");
     let flakepath = config
         .flake
         .as_ref()
         .map(PathBuf::from)
         .expect("Failed to get flake path");
-    let modules = modules::load::loadmodules(&flakepath)?;
+    let LoadedModules {
+        modules,
+        available,
+        diagnostics,
+    } = modules::load::loadmodules(&flakepath, &moduleroots)?;
     let current_config =
-        getcurrentoptions(&config, &modules).expect("Failed to load current module configuration");
+        getcurrentoptions(&modulefile, &modules).expect("Failed to load current module configuration");
     Ok(LoadOutput {
         config,
         moduleconfig,
-        modulepath,
+        modulefile,
         flakepath,
         modules,
+        available,
+        moduleroots,
+        diagnostics,
         current_config,
     })
 }
@@ -42,23 +54,32 @@ pub struct ReloadOutput {
     pub current_config: HashMap<String, ModuleOption>,
     pub moduleconfig: String,
     pub modules: Vec<Module>,
+    pub available: Vec<Module>,
+    pub diagnostics: Vec<LoadDiagnostic>,
 }
 
-pub fn reload(config: &NixDataConfig) -> Result<ReloadOutput> {
+pub fn reload(config: &NixDataConfig, moduleroots: &[ModuleRoot]) -> Result<ReloadOutput> {
     let flakepath = config
         .flake
         .as_ref()
         .map(PathBuf::from)
         .expect("Failed to get flake path");
-    let modules = modules::load::loadmodules(&flakepath)?;
-    let current_config = getcurrentoptions(config, &modules)
+    let LoadedModules {
+        modules,
+        available,
+        diagnostics,
+    } = modules::load::loadmodules(&flakepath, moduleroots)?;
+    let modulefile = ModuleConfigFile::from_config(config)?;
+    let current_config = getcurrentoptions(&modulefile, &modules)
         .expect("Failed to load current module configuration");
-    let moduleconfig = loadmoduleconfig(config).expect("Failed to load module config");
+    let moduleconfig = modulefile.read().expect("Failed to load module config");
     Ok(
         ReloadOutput {
             current_config,
             moduleconfig,
             modules,
+            available,
+            diagnostics,
         }
     )
 }
@@ -1,7 +1,11 @@
 pub mod window;
 pub mod modulecard_factory;
+pub mod diagnostic_factory;
 pub mod module;
+pub mod catalog;
+pub mod generations;
 pub mod rebuild;
 pub mod error_dialog;
+pub mod history;
 pub mod load;
 pub mod about;
@@ -9,6 +9,7 @@ use relm4::{
 
 pub struct ListOptionModel {
     value: String,
+    subtitle: Option<String>,
     index: DynamicIndex,
 }
 
@@ -22,6 +23,8 @@ pub enum ListOptionOutput {
 
 pub struct ListOptionInit {
     pub value: String,
+    /// Shown below the value, such as the value of a map entry
+    pub subtitle: Option<String>,
 }
 
 #[relm4::factory(pub)]
@@ -38,6 +41,7 @@ impl FactoryComponent for ListOptionModel {
         adw::ActionRow {
             #[watch]
             set_title: &self.value,
+            set_subtitle: self.subtitle.as_deref().unwrap_or_default(),
             add_suffix = &gtk::Button {
                 set_valign: gtk::Align::Center,
                 set_icon_name: "user-trash-symbolic",
@@ -51,6 +55,7 @@ impl FactoryComponent for ListOptionModel {
     fn init_model(init: Self::Init, index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
         Self {
             value: init.value,
+            subtitle: init.subtitle,
             index: index.clone(),
         }
     }
@@ -4,12 +4,15 @@ use adw::prelude::*;
 use log::debug;
 use relm4::{
     factory::{FactoryVecDeque, FactoryView},
-    gtk,
+    gtk::{self, gio, glib},
     prelude::{DynamicIndex, FactoryComponent},
     view, FactorySender,
 };
 
-use crate::modules::{ModuleOption, OptionData, OptionType};
+use crate::modules::{
+    secret::{secretpath, SecretValue},
+    ModuleOption, OptionData, OptionType, PathFormat, PathKind,
+};
 
 use super::{
     list_option_factory::{ListOptionInit, ListOptionModel},
@@ -29,6 +32,13 @@ pub struct ModuleOptionModel {
     list_option_factory: FactoryVecDeque<ListOptionModel>,
     entryerror: bool,
     entryinput: String,
+    entryvalue: String,
+    /// Whether the option is set in `modules.nix` rather than using the module default
+    explicit: bool,
+    #[tracker::no_eq]
+    defaultlabel: gtk::Label,
+    #[tracker::no_eq]
+    resetbutton: gtk::Button,
 }
 
 #[derive(Debug)]
@@ -37,19 +47,26 @@ pub enum ModuleOptionInput {
     SelectOption(u32),
     SetEntryError(bool),
     SetEntryInput(String),
+    SetEntryValue(String),
     AddExpanderOption,
     RemoveExpanderOption(String, DynamicIndex),
+    ToggleMultiEnum(String, bool),
+    SetExplicit(bool),
 }
 
 #[derive(Debug)]
 pub enum ModuleOptionOutput {
     SetOption(String, ModuleOption),
+    ResetOption(String),
+    /// The entered value is invalid, so there is no pending change for it
+    ClearOption(String),
 }
 
 #[derive(Debug)]
 pub struct ModuleOptionInit {
     pub data: OptionData,
     pub value: Option<ModuleOption>,
+    pub explicit: bool,
 }
 
 #[relm4::factory(pub)]
@@ -74,23 +91,22 @@ impl FactoryComponent for ModuleOptionModel {
     fn init_model(init: Self::Init, _index: &DynamicIndex, sender: FactorySender<Self>) -> Self {
         let mut list_option_factory =
             FactoryVecDeque::new(adw::ExpanderRow::new(), sender.input_sender());
-        if let OptionType::NumberList { default } = &init.data.op_type {
+        if let Some(entries) = listentries(&init.data.op_type, &init.value) {
             let mut list_option_factory_guard = list_option_factory.guard();
-            if let Some(ModuleOption::NumberList { value }) = &init.value {
-                for number in value {
-                    list_option_factory_guard.push_back(ListOptionInit {
-                        value: number.to_string(),
-                    });
-                }
-            } else {
-                for number in default {
-                    list_option_factory_guard.push_back(ListOptionInit {
-                        value: number.to_string(),
-                    });
-                }
+            for entry in entries {
+                list_option_factory_guard.push_back(entry);
             }
             list_option_factory_guard.drop();
         }
+        let defaultlabel = gtk::Label::new(Some("Default"));
+        defaultlabel.add_css_class("dim-label");
+        defaultlabel.add_css_class("caption");
+        defaultlabel.set_visible(!init.explicit);
+        let resetbutton = gtk::Button::from_icon_name("edit-undo-symbolic");
+        resetbutton.add_css_class("flat");
+        resetbutton.set_valign(gtk::Align::Center);
+        resetbutton.set_tooltip_text(Some("Reset to default"));
+        resetbutton.set_visible(init.explicit);
         Self {
             data: init.data.clone(),
             value: init.value,
@@ -100,6 +116,10 @@ impl FactoryComponent for ModuleOptionModel {
             list_option_factory,
             entryerror: false,
             entryinput: String::new(),
+            entryvalue: String::new(),
+            explicit: init.explicit,
+            defaultlabel,
+            resetbutton,
             tracker: 0,
         }
     }
@@ -115,12 +135,28 @@ impl FactoryComponent for ModuleOptionModel {
 
         let prefgroup = adw::PreferencesGroup::new();
 
+        view! {
+            reset_box = gtk::Box {
+                set_valign: gtk::Align::Center,
+                append: &self.defaultlabel,
+                append: &self.resetbutton,
+            }
+        }
+        {
+            let sender = sender.clone();
+            let id = self.data.id.to_string();
+            self.resetbutton.connect_clicked(move |_| {
+                sender.output(ModuleOptionOutput::ResetOption(id.to_string()));
+            });
+        }
+
         match &self.data.op_type {
             OptionType::Switch { default } => {
                 view! {
                     switch = adw::ActionRow {
                         set_hexpand: true,
                         set_title: &self.label,
+                        add_suffix: &reset_box,
                         #[watch]
                         set_subtitle: &self.subtitle,
                         add_suffix = & gtk::Switch {
@@ -142,16 +178,19 @@ impl FactoryComponent for ModuleOptionModel {
                 };
                 prefgroup.add(&switch);
             }
-            OptionType::Text { default } => {
+            OptionType::Text { default, placeholder, max_length, .. } => {
                 view! {
                     text = adw::ActionRow {
                         set_hexpand: true,
                         set_title: &self.label,
+                        add_suffix: &reset_box,
                         #[watch]
                         set_subtitle: &self.subtitle,
                         add_suffix = &gtk::Entry {
                             set_halign: gtk::Align::End,
                             set_valign: gtk::Align::Center,
+                            set_placeholder_text: placeholder.as_deref(),
+                            set_max_length: max_length.map(|x| x as i32).unwrap_or(0),
                             set_text: {
                                 if let Some(ModuleOption::Text { value }) = &self.value {
                                     value
@@ -159,8 +198,24 @@ impl FactoryComponent for ModuleOptionModel {
                                     default
                                 }
                             },
-                            connect_changed[sender, id = self.data.id.to_string()] => move |x| {
-                                sender.output(ModuleOptionOutput::SetOption(id.to_string(), ModuleOption::Text { value: x.text().to_string() }));
+                            connect_changed[sender, id = self.data.id.to_string(), op_type = self.data.op_type.clone(), subtitle = self.subtitle.clone()] => move |x| {
+                                let row = x.ancestor(adw::ActionRow::static_type()).and_then(|row| row.downcast::<adw::ActionRow>().ok());
+                                match op_type.check_text(&x.text()) {
+                                    Ok(()) => {
+                                        x.remove_css_class("error");
+                                        if let Some(row) = row {
+                                            row.set_subtitle(&subtitle);
+                                        }
+                                        sender.output(ModuleOptionOutput::SetOption(id.to_string(), ModuleOption::Text { value: x.text().to_string() }));
+                                    }
+                                    Err(e) => {
+                                        x.add_css_class("error");
+                                        if let Some(row) = row {
+                                            row.set_subtitle(&e.to_string());
+                                        }
+                                        sender.output(ModuleOptionOutput::ClearOption(id.to_string()));
+                                    }
+                                }
                             }
                         }
                     }
@@ -171,6 +226,7 @@ impl FactoryComponent for ModuleOptionModel {
                 view! {
                     string_enum = adw::ComboRow {
                         set_title: &self.label,
+                        add_suffix: &reset_box,
                         #[watch]
                         set_subtitle: &self.subtitle,
                         set_model: ({
@@ -207,11 +263,12 @@ impl FactoryComponent for ModuleOptionModel {
                 }
                 prefgroup.add(&string_enum);
             }
-            OptionType::NumberList { .. } => {
+            OptionType::NumberList { .. } | OptionType::StringList { .. } => {
                 view! {
                     #[local_ref]
                     list_option_exapnder -> adw::ExpanderRow {
                         set_title: &self.label,
+                        add_action: &reset_box,
                         #[watch]
                         set_subtitle: &self.subtitle,
                         set_expanded: true,
@@ -225,8 +282,8 @@ impl FactoryComponent for ModuleOptionModel {
                                 } else {
                                     &[]
                                 },
-                                connect_changed[sender] => move |x| {
-                                    let error = !x.text().is_empty() && x.text().parse::<u32>().is_err();
+                                connect_changed[sender, numeric = matches!(self.data.op_type, OptionType::NumberList { .. })] => move |x| {
+                                    let error = numeric && !x.text().is_empty() && x.text().parse::<u32>().is_err();
                                     sender.input(ModuleOptionInput::SetEntryError(error));
                                     if !error {
                                         sender.input(ModuleOptionInput::SetEntryInput(x.text().to_string()));
@@ -245,6 +302,235 @@ impl FactoryComponent for ModuleOptionModel {
                 }
                 prefgroup.add(list_option_exapnder);
             }
+            OptionType::Map { key_type, value_type, .. } => {
+                view! {
+                    #[local_ref]
+                    list_option_exapnder -> adw::ExpanderRow {
+                        set_title: &self.label,
+                        add_action: &reset_box,
+                        #[watch]
+                        set_subtitle: &self.subtitle,
+                        set_expanded: true,
+                        add_action = &gtk::Box {
+                            add_css_class: "linked",
+                            gtk::Entry {
+                                set_valign: gtk::Align::Center,
+                                set_placeholder_text: Some("Name"),
+                                set_width_chars: 10,
+                                connect_changed[sender, key_type = *key_type] => move |x| {
+                                    if x.text().is_empty() || key_type.parse(&x.text()).is_ok() {
+                                        x.remove_css_class("error");
+                                    } else {
+                                        x.add_css_class("error");
+                                    }
+                                    sender.input(ModuleOptionInput::SetEntryInput(x.text().to_string()));
+                                }
+                            },
+                            gtk::Entry {
+                                set_valign: gtk::Align::Center,
+                                set_placeholder_text: Some("Value"),
+                                set_width_chars: 10,
+                                connect_changed[sender, value_type = *value_type] => move |x| {
+                                    if x.text().is_empty() || value_type.parse(&x.text()).is_ok() {
+                                        x.remove_css_class("error");
+                                    } else {
+                                        x.add_css_class("error");
+                                    }
+                                    sender.input(ModuleOptionInput::SetEntryValue(x.text().to_string()));
+                                }
+                            },
+                            gtk::Button {
+                                set_icon_name: "list-add-symbolic",
+                                set_valign: gtk::Align::Center,
+                                set_tooltip_text: Some("Add or replace entry"),
+                                connect_clicked[sender] => move |_| {
+                                    sender.input(ModuleOptionInput::AddExpanderOption);
+                                }
+                            }
+                        }
+                    }
+                }
+                prefgroup.add(list_option_exapnder);
+            }
+            OptionType::MultiEnum { default, options } => {
+                let selected = if let Some(ModuleOption::MultiEnum { value }) = &self.value {
+                    value.clone()
+                } else {
+                    default.clone()
+                };
+                let mut entries = options.iter().collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.1.cmp(b.1));
+                view! {
+                    multi_enum = adw::ExpanderRow {
+                        set_title: &self.label,
+                        add_action: &reset_box,
+                        set_subtitle: &self.subtitle,
+                    }
+                }
+                for (key, pretty) in entries {
+                    view! {
+                        row = adw::ActionRow {
+                            set_title: pretty,
+                            add_prefix: check = &gtk::CheckButton {
+                                set_valign: gtk::Align::Center,
+                                set_active: selected.contains(key),
+                                connect_toggled[sender, key = key.to_string()] => move |x| {
+                                    sender.input(ModuleOptionInput::ToggleMultiEnum(key.to_string(), x.is_active()));
+                                }
+                            }
+                        }
+                    }
+                    row.set_activatable_widget(Some(&check));
+                    multi_enum.add_row(&row);
+                }
+                prefgroup.add(&multi_enum);
+            }
+            OptionType::Secret { .. } => {
+                view! {
+                    secret = adw::PasswordEntryRow {
+                        set_title: &if matches!(&self.value, Some(ModuleOption::Secret { path, .. }) if !path.is_empty()) {
+                            format!("{} (set)", self.label)
+                        } else {
+                            self.label.to_string()
+                        },
+                        add_suffix: &reset_box,
+                        set_tooltip_text: self.data.description.as_deref(),
+                        set_show_apply_button: true,
+                        connect_apply[sender, id = self.data.id.to_string()] => move |x| {
+                            if x.text().is_empty() {
+                                return;
+                            }
+                            let value = ModuleOption::Secret {
+                                path: secretpath(&id).to_string_lossy().to_string(),
+                                secret: Some(SecretValue::new(x.text().to_string())),
+                            };
+                            x.set_text("");
+                            sender.output(ModuleOptionOutput::SetOption(id.to_string(), value));
+                        }
+                    }
+                };
+                prefgroup.add(&secret);
+            }
+            OptionType::Path { default, kind, format, .. } => {
+                view! {
+                    path = adw::ActionRow {
+                        set_hexpand: true,
+                        set_title: &self.label,
+                        add_suffix: &reset_box,
+                        #[watch]
+                        set_subtitle: &self.subtitle,
+                        add_suffix: path_entry = &gtk::Entry {
+                            set_halign: gtk::Align::End,
+                            set_valign: gtk::Align::Center,
+                            set_text: {
+                                if let Some(ModuleOption::Path { value, .. }) = &self.value {
+                                    value
+                                } else {
+                                    default
+                                }
+                            },
+                            connect_changed[sender, id = self.data.id.to_string(), op_type = self.data.op_type.clone(), subtitle = self.subtitle.clone(), literal = *format == PathFormat::Path] => move |x| {
+                                let row = x.ancestor(adw::ActionRow::static_type()).and_then(|row| row.downcast::<adw::ActionRow>().ok());
+                                match op_type.check_path(&x.text()) {
+                                    Ok(()) => {
+                                        x.remove_css_class("error");
+                                        if let Some(row) = row {
+                                            row.set_subtitle(&subtitle);
+                                        }
+                                        sender.output(ModuleOptionOutput::SetOption(id.to_string(), ModuleOption::Path { value: x.text().to_string(), literal }));
+                                    }
+                                    Err(e) => {
+                                        x.add_css_class("error");
+                                        if let Some(row) = row {
+                                            row.set_subtitle(&e.to_string());
+                                        }
+                                        sender.output(ModuleOptionOutput::ClearOption(id.to_string()));
+                                    }
+                                }
+                            }
+                        },
+                        add_suffix = &gtk::Button {
+                            set_valign: gtk::Align::Center,
+                            set_icon_name: match kind {
+                                PathKind::File => "document-open-symbolic",
+                                PathKind::Directory => "folder-open-symbolic",
+                            },
+                            set_tooltip_text: Some(match kind {
+                                PathKind::File => "Choose File",
+                                PathKind::Directory => "Choose Folder",
+                            }),
+                            connect_clicked[path_entry, kind = *kind] => move |x| {
+                                let window = x.root().and_then(|root| root.downcast::<gtk::Window>().ok());
+                                let dialog = gtk::FileDialog::builder().modal(true).build();
+                                let current = gio::File::for_path(path_entry.text().as_str());
+                                let path_entry = path_entry.clone();
+                                let callback = move |result: Result<gio::File, glib::Error>| {
+                                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                                        path_entry.set_text(&path.to_string_lossy());
+                                    }
+                                };
+                                match kind {
+                                    PathKind::File => {
+                                        if current.query_exists(gio::Cancellable::NONE) {
+                                            dialog.set_initial_file(Some(&current));
+                                        }
+                                        dialog.open(window.as_ref(), gio::Cancellable::NONE, callback);
+                                    }
+                                    PathKind::Directory => {
+                                        if current.query_exists(gio::Cancellable::NONE) {
+                                            dialog.set_initial_folder(Some(&current));
+                                        }
+                                        dialog.select_folder(window.as_ref(), gio::Cancellable::NONE, callback);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                };
+                prefgroup.add(&path);
+            }
+            OptionType::Integer { default, min, max, step } => {
+                let step = step.filter(|x| *x > 0).unwrap_or(1) as f64;
+                view! {
+                    integer = adw::ActionRow {
+                        set_hexpand: true,
+                        set_title: &self.label,
+                        add_suffix: &reset_box,
+                        #[watch]
+                        set_subtitle: &self.subtitle,
+                        add_suffix = &gtk::SpinButton {
+                            set_halign: gtk::Align::End,
+                            set_valign: gtk::Align::Center,
+                            set_numeric: true,
+                            set_digits: 0,
+                            set_snap_to_ticks: true,
+                            set_adjustment: &gtk::Adjustment::new(
+                                if let Some(ModuleOption::Integer { value }) = self.value {
+                                    value as f64
+                                } else {
+                                    *default as f64
+                                },
+                                // Ticks snap relative to the lower bound, so keep it a multiple of step
+                                min.map(|x| x as f64).unwrap_or((i64::MIN as f64 / step).ceil() * step),
+                                max.map(|x| x as f64).unwrap_or(i64::MAX as f64),
+                                step,
+                                step * 10.0,
+                                0.0,
+                            ),
+                            connect_value_changed[sender, id = self.data.id.to_string(), op_type = self.data.op_type.clone()] => move |x| {
+                                // Values beyond 2^53 are rounded by the f64 adjustment, so check what is stored
+                                let value = x.value() as i64;
+                                if op_type.check_integer(value).is_ok() {
+                                    sender.output(ModuleOptionOutput::SetOption(id.to_string(), ModuleOption::Integer { value }));
+                                } else {
+                                    sender.output(ModuleOptionOutput::ClearOption(id.to_string()));
+                                }
+                            }
+                        }
+                    }
+                };
+                prefgroup.add(&integer);
+            }
         }
 
         let widgets = view_output!();
@@ -274,77 +560,137 @@ impl FactoryComponent for ModuleOptionModel {
             ModuleOptionInput::SetEntryInput(input) => {
                 self.set_entryinput(input);
             }
+            ModuleOptionInput::SetEntryValue(input) => {
+                self.set_entryvalue(input);
+            }
             ModuleOptionInput::AddExpanderOption => {
-                let mut list_option_factory_guard = self.list_option_factory.guard();
-                let entry = &self.entryinput;
-                if let Ok(number) = entry.parse::<u32>() {
-                    if let Some(ModuleOption::NumberList { mut value }) = self.value.clone() {
-                        if !value.contains(&number) {
-                            value.push(entry.parse::<u32>().unwrap());
-                            value.sort();
-                            sender.output(ModuleOptionOutput::SetOption(
-                                self.data.id.to_string(),
-                                ModuleOption::NumberList {
-                                    value: value.to_vec(),
-                                },
-                            ));
-                            list_option_factory_guard.push_back(ListOptionInit {
-                                value: entry.to_string(),
-                            });
-                            list_option_factory_guard.drop();
-                            self.set_value(Some(ModuleOption::NumberList {
-                                value: value.to_vec(),
-                            }));
+                let entry = self.entryinput.trim().to_string();
+                let value = match &self.data.op_type {
+                    OptionType::NumberList { default } => {
+                        let mut value = if let Some(ModuleOption::NumberList { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        match entry.parse::<u32>() {
+                            Ok(number) if !value.contains(&number) => {
+                                value.push(number);
+                                value.sort();
+                                Some(ModuleOption::NumberList { value })
+                            }
+                            _ => None,
+                        }
+                    }
+                    OptionType::StringList { default } => {
+                        let mut value = if let Some(ModuleOption::StringList { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        if entry.is_empty() || value.contains(&entry) {
+                            None
+                        } else {
+                            value.push(entry.to_string());
+                            Some(ModuleOption::StringList { value })
+                        }
+                    }
+                    OptionType::Map { key_type, value_type, default } => {
+                        let mut value = if let Some(ModuleOption::Map { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        match (key_type.parse(&entry), value_type.parse(&self.entryvalue)) {
+                            (Ok(key), Ok(entryvalue)) if !entry.is_empty() => {
+                                value.insert(key.to_string(), entryvalue);
+                                Some(ModuleOption::Map { value })
+                            }
+                            _ => None,
                         }
-                    } else if let OptionType::NumberList { default } = &self.data.op_type {
-                        let mut value = default.clone();
-                        value.push(number);
-                        value.sort();
-                        sender.output(ModuleOptionOutput::SetOption(
-                            self.data.id.to_string(),
-                            ModuleOption::NumberList {
-                                value: value.to_vec(),
-                            },
-                        ));
-                        list_option_factory_guard.push_back(ListOptionInit {
-                            value: entry.to_string(),
-                        });
-                        list_option_factory_guard.drop();
-                        self.set_value(Some(ModuleOption::NumberList {
-                            value: value.to_vec(),
-                        }));
                     }
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    sender.output(ModuleOptionOutput::SetOption(
+                        self.data.id.to_string(),
+                        value.clone(),
+                    ));
+                    let value = Some(value);
+                    let mut list_option_factory_guard = self.list_option_factory.guard();
+                    list_option_factory_guard.clear();
+                    for entry in listentries(&self.data.op_type, &value).unwrap_or_default() {
+                        list_option_factory_guard.push_back(entry);
+                    }
+                    list_option_factory_guard.drop();
+                    self.set_value(value);
                 }
             }
             ModuleOptionInput::RemoveExpanderOption(entry, index) => {
-                let mut list_option_factory_guard = self.list_option_factory.guard();
-                if let Some(ModuleOption::NumberList { mut value }) = self.value.clone() {
-                    value.retain(|x| x.to_string() != entry);
+                let value = match &self.data.op_type {
+                    OptionType::NumberList { default } => {
+                        let mut value = if let Some(ModuleOption::NumberList { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        value.retain(|x| x.to_string() != entry);
+                        Some(ModuleOption::NumberList { value })
+                    }
+                    OptionType::StringList { default } => {
+                        let mut value = if let Some(ModuleOption::StringList { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        value.retain(|x| *x != entry);
+                        Some(ModuleOption::StringList { value })
+                    }
+                    OptionType::Map { default, .. } => {
+                        let mut value = if let Some(ModuleOption::Map { value }) = &self.value {
+                            value.clone()
+                        } else {
+                            default.clone()
+                        };
+                        value.remove(&entry);
+                        Some(ModuleOption::Map { value })
+                    }
+                    _ => None,
+                };
+                if let Some(value) = value {
                     sender.output(ModuleOptionOutput::SetOption(
                         self.data.id.to_string(),
-                        ModuleOption::NumberList {
-                            value: value.to_vec(),
-                        },
+                        value.clone(),
                     ));
+                    let mut list_option_factory_guard = self.list_option_factory.guard();
                     list_option_factory_guard.remove(index.current_index());
                     list_option_factory_guard.drop();
-                    self.set_value(Some(ModuleOption::NumberList {
-                        value: value.to_vec(),
-                    }));
-                } else if let OptionType::NumberList { default } = &self.data.op_type {
-                    let mut value = default.clone();
-                    value.retain(|x| x.to_string() != entry);
+                    self.set_value(Some(value));
+                }
+            }
+            ModuleOptionInput::SetExplicit(explicit) => {
+                self.set_explicit(explicit);
+                self.defaultlabel.set_visible(!explicit);
+                self.resetbutton.set_visible(explicit);
+            }
+            ModuleOptionInput::ToggleMultiEnum(key, active) => {
+                if let OptionType::MultiEnum { default, options } = &self.data.op_type {
+                    let mut value = if let Some(ModuleOption::MultiEnum { value }) = &self.value {
+                        value.clone()
+                    } else {
+                        default.clone()
+                    };
+                    if active && !value.contains(&key) {
+                        value.push(key);
+                    } else if !active {
+                        value.retain(|x| *x != key);
+                    }
+                    value.sort_by_key(|x| options.get(x).cloned().unwrap_or_default());
+                    let value = ModuleOption::MultiEnum { value };
                     sender.output(ModuleOptionOutput::SetOption(
                         self.data.id.to_string(),
-                        ModuleOption::NumberList {
-                            value: value.to_vec(),
-                        },
+                        value.clone(),
                     ));
-                    list_option_factory_guard.remove(index.current_index());
-                    list_option_factory_guard.drop();
-                    self.set_value(Some(ModuleOption::NumberList {
-                        value: value.to_vec(),
-                    }));
+                    self.set_value(Some(value));
                 }
             }
         }
@@ -353,7 +699,45 @@ impl FactoryComponent for ModuleOptionModel {
     fn forward_to_parent(output: Self::Output) -> Option<Self::ParentInput> {
         let output = match output {
             ModuleOptionOutput::SetOption(id, value) => ModulePageInput::SetModuleOption(id, value),
+            ModuleOptionOutput::ResetOption(id) => ModulePageInput::ResetModuleOption(id),
+            ModuleOptionOutput::ClearOption(id) => ModulePageInput::ClearModuleOption(id),
         };
         Some(output)
     }
 }
+
+/// Entries shown in the expander row of a list or map option.
+fn listentries(op_type: &OptionType, value: &Option<ModuleOption>) -> Option<Vec<ListOptionInit>> {
+    let entry = |value: String| ListOptionInit {
+        value,
+        subtitle: None,
+    };
+    match (op_type, value) {
+        (OptionType::NumberList { .. }, Some(ModuleOption::NumberList { value })) => {
+            Some(value.iter().map(|x| entry(x.to_string())).collect())
+        }
+        (OptionType::NumberList { default }, _) => {
+            Some(default.iter().map(|x| entry(x.to_string())).collect())
+        }
+        (OptionType::StringList { .. }, Some(ModuleOption::StringList { value })) => {
+            Some(value.iter().cloned().map(entry).collect())
+        }
+        (OptionType::StringList { default }, _) => Some(default.iter().cloned().map(entry).collect()),
+        (OptionType::Map { default, .. }, value) => {
+            let value = match value {
+                Some(ModuleOption::Map { value }) => value,
+                _ => default,
+            };
+            Some(
+                value
+                    .iter()
+                    .map(|(key, value)| ListOptionInit {
+                        value: key.to_string(),
+                        subtitle: Some(value.to_string()),
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
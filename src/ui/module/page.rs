@@ -4,13 +4,14 @@ use adw::prelude::{OrientableExt, WidgetExt, ButtonExt, BoxExt};
 use log::error;
 use relm4::{gtk, SimpleComponent, ComponentSender, ComponentParts, RelmWidgetExt, factory::FactoryVecDeque};
 
-use crate::{modules::{ModuleData, ModuleOption}, ui::{window::AppInput, module::option_factory::ModuleOptionInit}};
+use crate::{modules::{Module, ModuleData, ModuleOption, PendingChange}, ui::{window::AppInput, module::option_factory::{ModuleOptionInit, ModuleOptionInput}}};
 
 use super::option_factory::ModuleOptionModel;
 
 #[tracker::track]
 pub struct ModulePageModel {
     data: Option<ModuleData>,
+    source: String,
     #[tracker::no_eq]
     optionfactory: FactoryVecDeque<ModuleOptionModel>,
     show_apply: bool,
@@ -18,8 +19,10 @@ pub struct ModulePageModel {
 
 #[derive(Debug)]
 pub enum ModulePageInput {
-    OpenModulePage(ModuleData, HashMap<String, ModuleOption>, HashMap<String, ModuleOption>),
+    OpenModulePage(Module, HashMap<String, ModuleOption>, HashMap<String, PendingChange>),
     SetModuleOption(String, ModuleOption),
+    ResetModuleOption(String),
+    ClearModuleOption(String),
     ShowApply(bool),
 }
 
@@ -51,6 +54,18 @@ impl SimpleComponent for ModulePageModel {
                         let _ = sender.output(AppInput::CloseModulePage);
                     },
                 },
+                pack_start = &gtk::Button {
+                    add_css_class: "flat",
+                    set_icon_name: "edit-undo-symbolic",
+                    set_tooltip_text: Some("Undo"),
+                    set_action_name: Some("edit.undo"),
+                },
+                pack_start = &gtk::Button {
+                    add_css_class: "flat",
+                    set_icon_name: "edit-redo-symbolic",
+                    set_tooltip_text: Some("Redo"),
+                    set_action_name: Some("edit.redo"),
+                },
                 pack_end = &gtk::Button {
                     #[track(model.changed(ModulePageModel::show_apply()))]
                     set_visible: model.show_apply,
@@ -59,6 +74,15 @@ impl SimpleComponent for ModulePageModel {
                     connect_clicked[sender] => move |_| {
                         if sender.output(AppInput::ApplyChanges).is_err() { error!("Error sender AppInput::Applychanges") }
                     }
+                },
+                pack_end = &gtk::Button {
+                    #[track(model.changed(ModulePageModel::show_apply()))]
+                    set_visible: model.show_apply,
+                    set_label: "Discard",
+                    set_tooltip_text: Some("Discard changes"),
+                    connect_clicked[sender] => move |_| {
+                        if sender.output(AppInput::DiscardChanges).is_err() { error!("Error sending AppInput::DiscardChanges") }
+                    }
                 }
             },
             gtk::ScrolledWindow {
@@ -82,6 +106,14 @@ impl SimpleComponent for ModulePageModel {
                             #[track(model.changed(ModulePageModel::data()))]
                             set_label: model.data.as_ref().and_then(|data| data.description.as_deref()).unwrap_or_default(),
                         },
+                        gtk::Label {
+                            add_css_class: "caption",
+                            add_css_class: "dim-label",
+                            set_halign: gtk::Align::Start,
+                            set_margin_top: 5,
+                            #[track(model.changed(ModulePageModel::source()))]
+                            set_label: &model.source,
+                        },
                         #[local_ref]
                         optionfactory_box -> gtk::Box {
                             set_orientation: gtk::Orientation::Vertical,
@@ -103,6 +135,7 @@ impl SimpleComponent for ModulePageModel {
         let optionfactory = FactoryVecDeque::new(gtk::Box::new(gtk::Orientation::Vertical, 0), sender.input_sender());
         let model = ModulePageModel {
             data: None,
+            source: String::new(),
             optionfactory,
             show_apply: false,
             tracker: 0,
@@ -115,24 +148,38 @@ impl SimpleComponent for ModulePageModel {
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
         self.reset();
         match message {
-            ModulePageInput::OpenModulePage(data, current_config, modified_config) => {
-                self.set_data(Some(data));
+            ModulePageInput::OpenModulePage(module, current_config, modified_config) => {
+                self.set_source(format!("Loaded from {}", module.root));
+                self.set_data(Some(module.config));
                 let mut optionfactory_guard = self.optionfactory.guard();
                 optionfactory_guard.clear();
                 if let Some(options) = self.data.as_ref().map(|x| x.options.to_vec()) {
                     for option in options {
-                        let modified_value = modified_config.get(&option.id);
-                        let value = current_config.get(&option.id);
+                        let (value, explicit) = match modified_config.get(&option.id) {
+                            Some(change) => (change.value().cloned(), change.value().is_some()),
+                            None => (current_config.get(&option.id).cloned(), current_config.contains_key(&option.id)),
+                        };
                         optionfactory_guard.push_back(ModuleOptionInit {
                             data: option,
-                            value: modified_value.cloned().or(value.cloned())
+                            value,
+                            explicit,
                         });
                     }
                 }
             },
             ModulePageInput::SetModuleOption(id, value) => {
+                // Options are listed in the order of the module data
+                if let Some(index) = self.data.as_ref().and_then(|data| data.options.iter().position(|x| x.id == id)) {
+                    self.optionfactory.send(index, ModuleOptionInput::SetExplicit(true));
+                }
                 if sender.output(AppInput::SetModuleOption(id, value)).is_err() { error!("Error sending: AppInput::SetModuleOption") }
             },
+            ModulePageInput::ResetModuleOption(id) => {
+                if sender.output(AppInput::ResetModuleOption(id)).is_err() { error!("Error sending: AppInput::ResetModuleOption") }
+            },
+            ModulePageInput::ClearModuleOption(id) => {
+                if sender.output(AppInput::ClearModuleOption(id)).is_err() { error!("Error sending: AppInput::ClearModuleOption") }
+            },
             ModulePageInput::ShowApply(show) => {
                 self.set_show_apply(show)
             }
@@ -1,5 +1,5 @@
 use super::window::AppInput;
-use crate::modules::{IconType, Module, ModuleData};
+use crate::modules::{IconType, Module};
 use adw::prelude::{BoxExt, ButtonExt, OrientableExt, WidgetExt};
 use log::debug;
 use relm4::{
@@ -18,7 +18,7 @@ pub enum ModuleCardInput {}
 
 #[derive(Debug)]
 pub enum ModuleCardOutput {
-    Clicked(ModuleData),
+    Clicked(Module),
 }
 
 pub struct ModuleCardInit {
@@ -38,8 +38,8 @@ impl FactoryComponent for ModuleCardModel {
         #[root]
         gtk::Button {
             add_css_class: "card",
-            connect_clicked[sender, data = self.module.config.clone()] => move |_| {
-                sender.output(ModuleCardOutput::Clicked(data.clone()))
+            connect_clicked[sender, module = self.module.clone()] => move |_| {
+                sender.output(ModuleCardOutput::Clicked(module.clone()))
             },
             gtk::Box {
                 set_orientation: gtk::Orientation::Horizontal,
@@ -118,7 +118,7 @@ impl FactoryComponent for ModuleCardModel {
 
     fn forward_to_parent(output: Self::Output) -> Option<Self::ParentInput> {
         let output = match output {
-            ModuleCardOutput::Clicked(data) => AppInput::OpenModulePage(data),
+            ModuleCardOutput::Clicked(module) => AppInput::OpenModulePage(module),
         };
         Some(output)
     }
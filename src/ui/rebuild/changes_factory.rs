@@ -47,9 +47,26 @@ impl FactoryComponent for ModuleChangesModel {
             FactoryVecDeque::new(adw::PreferencesGroup::new(), sender.input_sender());
         let mut modificationfactory_guard = modificationfactory.guard();
         for change in init.modifications {
-            modificationfactory_guard.push_back(OptionChangesInit {
-                modification: change,
-            });
+            match change.mod_type {
+                ModificationType::MapUpdate { entries } => {
+                    for (key, mod_type) in entries {
+                        modificationfactory_guard.push_back(OptionChangesInit {
+                            modification: OptionModification {
+                                label: format!("{} › {}", change.label, key),
+                                mod_type,
+                            },
+                        });
+                    }
+                }
+                mod_type => {
+                    modificationfactory_guard.push_back(OptionChangesInit {
+                        modification: OptionModification {
+                            label: change.label,
+                            mod_type,
+                        },
+                    });
+                }
+            }
         }
         modificationfactory_guard.drop();
         Self {
@@ -1,6 +1,9 @@
-use super::{changes_factory::ModuleChangesModel, ModificationType};
+use super::{changes_factory::ModuleChangesModel, ModificationType, RebuildMode};
 use crate::{
-    modules::{Module, ModuleOption},
+    modules::{
+        configfile::{applychanges, unifieddiff},
+        Module, ModuleOption, OptionType, PendingChange,
+    },
     ui::{
         rebuild::{changes_factory::ModuleChangesInit, OptionModification},
         window::AppInput,
@@ -12,18 +15,27 @@ use std::collections::HashMap;
 
 pub struct ConfirmDialogModel {
     modules: Vec<Module>,
+    modulepath: String,
     visible: bool,
+    mode: RebuildMode,
+    /// Changes to `modules.nix` as a unified diff
+    diff: String,
     changes_factory: FactoryVecDeque<ModuleChangesModel>,
 }
 
 #[derive(Debug)]
 pub enum ConfirmDialogInput {
-    Open(HashMap<String, ModuleOption>, HashMap<String, ModuleOption>),
+    /// Current options, pending changes, flake module changes and current `modules.nix`
+    Open(HashMap<String, ModuleOption>, HashMap<String, PendingChange>, HashMap<String, bool>, String),
+    SetModules(Vec<Module>),
+    SetMode(RebuildMode),
+    Continue,
     Close,
 }
 
 pub struct ConfirmDialogInit {
     pub modules: Vec<Module>,
+    pub modulepath: String,
 }
 
 #[relm4::component(pub)]
@@ -41,10 +53,49 @@ impl SimpleComponent for ConfirmDialogModel {
             set_heading: Some("Apply changes?"),
             set_body: "The following changes will be applied. This may take some time.",
             #[wrap(Some)]
-            #[local_ref]
-            set_extra_child = changes_factory_box -> gtk::Box {
+            set_extra_child = &gtk::Box {
                 set_orientation: gtk::Orientation::Vertical,
                 set_spacing: 15,
+                #[local_ref]
+                changes_factory_box -> gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 15,
+                },
+                adw::PreferencesGroup {
+                    adw::ComboRow {
+                        set_title: "Mode",
+                        set_model: Some(&gtk::StringList::new(
+                            &RebuildMode::ALL.iter().map(|x| x.label()).collect::<Vec<_>>(),
+                        )),
+                        #[watch]
+                        set_selected: RebuildMode::ALL.iter().position(|x| *x == model.mode).unwrap_or(0) as u32,
+                        connect_selected_notify[sender] => move |x| {
+                            if let Some(mode) = RebuildMode::ALL.get(x.selected() as usize) {
+                                sender.input(ConfirmDialogInput::SetMode(*mode));
+                            }
+                        }
+                    }
+                },
+                gtk::Expander {
+                    set_label: Some("Show changes to modules.nix"),
+                    #[watch]
+                    set_visible: !model.diff.is_empty(),
+                    #[wrap(Some)]
+                    set_child = &gtk::ScrolledWindow {
+                        set_min_content_height: 200,
+                        set_max_content_height: 400,
+                        set_propagate_natural_height: true,
+                        gtk::Label {
+                            add_css_class: "monospace",
+                            set_halign: gtk::Align::Start,
+                            set_valign: gtk::Align::Start,
+                            set_xalign: 0.0,
+                            set_selectable: true,
+                            #[watch]
+                            set_label: &model.diff,
+                        }
+                    }
+                }
             },
             add_response: ("cancel", "Cancel"),
             add_response: ("continue", "Continue"),
@@ -64,30 +115,60 @@ impl SimpleComponent for ConfirmDialogModel {
             FactoryVecDeque::new(gtk::Box::builder().build(), sender.input_sender());
         let model = ConfirmDialogModel {
             modules: init.modules,
+            modulepath: init.modulepath,
             visible: false,
+            mode: RebuildMode::default(),
+            diff: String::new(),
             changes_factory,
         };
         let changes_factory_box = model.changes_factory.widget();
         let widgets = view_output!();
-        widgets
-            .dialog
-            .connect_response(None, move |_, resp| match resp {
-                "cancel" => sender.input(ConfirmDialogInput::Close),
-                "continue" => {
-                    sender.input(ConfirmDialogInput::Close);
-                    let _ = sender.output(AppInput::Rebuild);
-                }
-                _ => unreachable!(),
-            });
+        {
+            let sender = sender.clone();
+            widgets
+                .dialog
+                .connect_response(None, move |_, resp| match resp {
+                    "cancel" => sender.input(ConfirmDialogInput::Close),
+                    "continue" => sender.input(ConfirmDialogInput::Continue),
+                    _ => unreachable!(),
+                });
+        }
         ComponentParts { model, widgets }
     }
 
-    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>) {
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
         match message {
-            ConfirmDialogInput::Open(current_config, modified_config) => {
+            ConfirmDialogInput::Open(current_config, modified_config, modified_modules, moduleconfig) => {
                 self.visible = true;
+                self.mode = RebuildMode::default();
+                self.diff = match applychanges(&moduleconfig, &modified_config) {
+                    Ok(output) => unifieddiff(&moduleconfig, &output, &self.modulepath, "new"),
+                    Err(e) => format!("{:#}", e),
+                };
                 let mut changes_factory_guard = self.changes_factory.guard();
                 changes_factory_guard.clear();
+                let module_changes = self
+                    .modules
+                    .iter()
+                    .filter_map(|module| {
+                        modified_modules
+                            .get(&module.flakeattr)
+                            .map(|install| OptionModification {
+                                label: module.config.name.to_string(),
+                                mod_type: if *install {
+                                    ModificationType::ModuleAdded
+                                } else {
+                                    ModificationType::ModuleRemoved
+                                },
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                if !module_changes.is_empty() {
+                    changes_factory_guard.push_back(ModuleChangesInit {
+                        modifications: module_changes,
+                        label: String::from("Modules"),
+                    });
+                }
                 for module in &self.modules {
                     let options = &module.config.options;
                     let matches = options
@@ -97,15 +178,73 @@ impl SimpleComponent for ConfirmDialogModel {
                                 .get(&x.id)
                                 .map(|modified_value| OptionModification {
                                     label: x.label.to_string(),
-                                    mod_type: if let Some(current_value) = current_config.get(&x.id)
-                                    {
-                                        ModificationType::Update {
-                                            new: modified_value.to_string(),
-                                            old: current_value.to_string(),
+                                    mod_type: if let PendingChange::Set(modified_value) = modified_value {
+                                        if let (
+                                            OptionType::MultiEnum { options, .. },
+                                            ModuleOption::MultiEnum { value: new },
+                                        ) = (&x.op_type, modified_value)
+                                        {
+                                            let old = match current_config.get(&x.id) {
+                                                Some(ModuleOption::MultiEnum { value }) => value.clone(),
+                                                _ => match x.op_type.default_value() {
+                                                    ModuleOption::MultiEnum { value } => value,
+                                                    _ => vec![],
+                                                },
+                                            };
+                                            let pretty = |key: &String| {
+                                                options.get(key).cloned().unwrap_or_else(|| key.to_string())
+                                            };
+                                            ModificationType::ListUpdate {
+                                                added: new.iter().filter(|v| !old.contains(v)).map(pretty).collect(),
+                                                removed: old.iter().filter(|v| !new.contains(v)).map(pretty).collect(),
+                                            }
+                                        } else if let ModuleOption::Secret { .. } = modified_value {
+                                            ModificationType::SecretUpdate
+                                        } else if let ModuleOption::Map { value: new } = modified_value {
+                                            let old = match current_config.get(&x.id) {
+                                                Some(ModuleOption::Map { value }) => value.clone(),
+                                                _ => match x.op_type.default_value() {
+                                                    ModuleOption::Map { value } => value,
+                                                    _ => Default::default(),
+                                                },
+                                            };
+                                            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+                                            keys.sort();
+                                            keys.dedup();
+                                            ModificationType::MapUpdate {
+                                                entries: keys
+                                                    .into_iter()
+                                                    .filter_map(|key| {
+                                                        let mod_type = match (old.get(key), new.get(key)) {
+                                                            (Some(old), Some(new)) if old != new => ModificationType::Update {
+                                                                old: old.to_string(),
+                                                                new: new.to_string(),
+                                                            },
+                                                            (None, Some(new)) => ModificationType::New { value: new.to_string() },
+                                                            (Some(old), None) => ModificationType::Remove { old: old.to_string() },
+                                                            _ => return None,
+                                                        };
+                                                        Some((key.to_string(), mod_type))
+                                                    })
+                                                    .collect(),
+                                            }
+                                        } else if let Some(current_value) = current_config.get(&x.id)
+                                        {
+                                            ModificationType::Update {
+                                                new: modified_value.to_string(),
+                                                old: current_value.to_string(),
+                                            }
+                                        } else {
+                                            ModificationType::New {
+                                                value: modified_value.to_string(),
+                                            }
                                         }
                                     } else {
-                                        ModificationType::New {
-                                            value: modified_value.to_string(),
+                                        ModificationType::Remove {
+                                            old: current_config
+                                                .get(&x.id)
+                                                .map(|x| x.to_string())
+                                                .unwrap_or_default(),
                                         }
                                     },
                                 })
@@ -119,6 +258,12 @@ impl SimpleComponent for ConfirmDialogModel {
                     }
                 }
             }
+            ConfirmDialogInput::SetModules(modules) => self.modules = modules,
+            ConfirmDialogInput::SetMode(mode) => self.mode = mode,
+            ConfirmDialogInput::Continue => {
+                self.visible = false;
+                let _ = sender.output(AppInput::Rebuild(self.mode));
+            }
             ConfirmDialogInput::Close => self.visible = false,
         }
     }
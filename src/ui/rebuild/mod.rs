@@ -12,6 +12,15 @@ pub struct OptionModification {
 pub enum ModificationType {
     New { value: String },
     Update { old: String, new: String },
+    /// Items added to and removed from a list
+    ListUpdate { added: Vec<String>, removed: Vec<String> },
+    Remove { old: String },
+    /// A new secret, whose value is never shown
+    SecretUpdate,
+    /// Changes to the entries of a map, shown as one row per entry
+    MapUpdate { entries: Vec<(String, ModificationType)> },
+    ModuleAdded,
+    ModuleRemoved,
 }
 
 impl ModificationType {
@@ -20,6 +29,63 @@ impl ModificationType {
         match self {
             ModificationType::New { value } => value.to_string(),
             ModificationType::Update { old, new } => format!("{} → {}", old, new),
+            ModificationType::ListUpdate { added, removed } => added
+                .iter()
+                .map(|x| format!("+ {}", x))
+                .chain(removed.iter().map(|x| format!("− {}", x)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            ModificationType::Remove { old } => format!("− {}", old),
+            ModificationType::SecretUpdate => String::from("New secret"),
+            ModificationType::MapUpdate { entries } => entries
+                .iter()
+                .map(|(key, x)| format!("{}: {}", key, x.to_string()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            ModificationType::ModuleAdded => String::from("Add to system"),
+            ModificationType::ModuleRemoved => String::from("Remove from system"),
+        }
+    }
+}
+
+/// How the system is rebuilt with the changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RebuildMode {
+    /// Activate the changes and make them the boot default
+    #[default]
+    Switch,
+    /// Only check that the system builds. `modules.nix` is restored afterwards.
+    Build,
+    /// Activate the changes without making them the boot default
+    Test,
+    /// Make the changes the boot default without activating them
+    Boot,
+}
+
+impl RebuildMode {
+    pub const ALL: [RebuildMode; 4] = [
+        RebuildMode::Switch,
+        RebuildMode::Build,
+        RebuildMode::Test,
+        RebuildMode::Boot,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RebuildMode::Switch => "Apply now",
+            RebuildMode::Build => "Test build only",
+            RebuildMode::Test => "Activate without making default",
+            RebuildMode::Boot => "Apply on next boot",
+        }
+    }
+
+    /// `nixos-rebuild` subcommand
+    pub fn command(&self) -> &'static str {
+        match self {
+            RebuildMode::Switch => "switch",
+            RebuildMode::Build => "build",
+            RebuildMode::Test => "test",
+            RebuildMode::Boot => "boot",
         }
     }
 }
@@ -1,5 +1,12 @@
+use super::RebuildMode;
 use crate::{
-    modules::ModuleOption,
+    modules::{
+        configfile::applychanges,
+        flake::setmodulesinstalled,
+        progress::{ProgressEvent, ProgressFile},
+        secret::StagedSecret,
+        ModuleOption, PendingChange,
+    },
     ui::window::AppInput, config::LIBEXECDIR,
 };
 use adw::{gio, glib};
@@ -11,7 +18,7 @@ use relm4::{
     },
     ComponentParts, ComponentSender, SimpleComponent,
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
 use vte::{TerminalExt, TerminalExtManual};
 
 #[tracker::track]
@@ -23,13 +30,38 @@ pub struct RebuildModel {
     flakepath: PathBuf,
     modulepath: PathBuf,
     generations: Option<u32>,
+    mode: RebuildMode,
+    /// Whether the running task is a rollback instead of a rebuild
+    rollback: bool,
+    /// Secrets passed to the running helper, removed once it exits
+    #[tracker::no_eq]
+    secrets: Vec<StagedSecret>,
+    /// Progress reported by the running helper
+    #[tracker::no_eq]
+    progress: Option<ProgressFile>,
+    #[tracker::no_eq]
+    poll: Option<glib::SourceId>,
+    #[tracker::no_eq]
+    progressbar: gtk::ProgressBar,
+    /// Whether the current phase has no steps to count, so the progress bar pulses
+    pulsing: bool,
+    phase: String,
+    /// Error reported by the helper
+    error: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum RebuildInput {
-    Rebuild(HashMap<String, ModuleOption>, String),
+    /// Pending option changes, current `modules.nix`, flake module changes and how to rebuild
+    Rebuild(HashMap<String, PendingChange>, String, HashMap<String, bool>, RebuildMode),
+    /// Switch to the given generation, or the previous one
+    Rollback(Option<u32>),
     Close,
     SetStatus(RebuildStatus),
+    /// The rebuild could not be started, show why
+    Fail(String),
+    /// Read new progress events
+    Poll,
 }
 
 pub struct RebuildInit {
@@ -92,41 +124,73 @@ impl SimpleComponent for RebuildModel {
                     gtk::Label {
                         add_css_class: "title-2",
                         #[track(model.changed(RebuildModel::status()))]
-                        set_text: match model.status {
-                            RebuildStatus::Building => "Rebuilding",
-                            RebuildStatus::Success => "Done!",
-                            RebuildStatus::Error => "Error!",
+                        set_text: match (&model.status, model.rollback) {
+                            (RebuildStatus::Building, true) => "Rolling back",
+                            (RebuildStatus::Building, false) => "Rebuilding",
+                            (RebuildStatus::Success, _) => "Done!",
+                            (RebuildStatus::Error, _) => "Error!",
                         }
                     },
                     gtk::Label {
                         #[track(model.changed(RebuildModel::status()))]
-                        set_text: match model.status {
-                            RebuildStatus::Building => "This may take a few minutes.",
-                            RebuildStatus::Success => "All changes have applied!",
-                            RebuildStatus::Error => "Error encountered during rebuild process."
+                        set_text: match (&model.status, model.mode) {
+                            (RebuildStatus::Building, _) => "This may take a few minutes.",
+                            (RebuildStatus::Success, _) if model.rollback => "The system was rolled back.",
+                            (RebuildStatus::Error, _) if model.rollback => "Error encountered during rollback.",
+                            (RebuildStatus::Success, RebuildMode::Switch) => "All changes have applied!",
+                            (RebuildStatus::Success, RebuildMode::Build) => "The system builds with these changes. Nothing was applied.",
+                            (RebuildStatus::Success, RebuildMode::Test) => "All changes are active until the next reboot.",
+                            (RebuildStatus::Success, RebuildMode::Boot) => "All changes will apply on the next boot.",
+                            (RebuildStatus::Error, _) => "Error encountered during rebuild process."
                         },
+                    },
+                    gtk::Label {
+                        add_css_class: "dim-label",
+                        set_wrap: true,
+                        #[track(model.changed(RebuildModel::error()))]
+                        set_visible: model.error.is_some(),
+                        #[track(model.changed(RebuildModel::error()))]
+                        set_text: model.error.as_deref().unwrap_or_default(),
+                    },
+                    gtk::Label {
+                        set_margin_top: 10,
+                        #[track(model.changed(RebuildModel::status()) || model.changed(RebuildModel::phase()))]
+                        set_visible: model.status == RebuildStatus::Building && !model.phase.is_empty(),
+                        #[track(model.changed(RebuildModel::phase()))]
+                        set_text: &model.phase,
+                    },
+                    #[local_ref]
+                    progressbar -> gtk::ProgressBar {
+                        #[track(model.changed(RebuildModel::status()))]
+                        set_visible: model.status == RebuildStatus::Building,
                     }
                 },
-                gtk::Frame {
+                gtk::Expander {
+                    set_label: Some("Show details"),
                     set_margin_start: 15,
                     set_margin_end: 15,
                     set_margin_bottom: 15,
-                    gtk::ScrolledWindow {
-                        set_min_content_height: 80,
-                        #[local_ref]
-                        terminal -> vte::Terminal {
-                            set_vexpand: true,
-                            set_hexpand: true,
-                            set_input_enabled: false,
-                            connect_child_exited[sender, rebuild_status = model.status.clone()] => move |_term, status| {
-                                if status == 0 {
-                                    info!("Rebuild finished successfully");
-                                    if rebuild_status == RebuildStatus::Building {
-                                        sender.input(RebuildInput::SetStatus(RebuildStatus::Success));
+                    set_vexpand: true,
+                    #[wrap(Some)]
+                    set_child = &gtk::Frame {
+                        set_margin_top: 10,
+                        gtk::ScrolledWindow {
+                            set_min_content_height: 80,
+                            #[local_ref]
+                            terminal -> vte::Terminal {
+                                set_vexpand: true,
+                                set_hexpand: true,
+                                set_input_enabled: false,
+                                connect_child_exited[sender, rebuild_status = model.status.clone()] => move |_term, status| {
+                                    if status == 0 {
+                                        info!("Rebuild finished successfully");
+                                        if rebuild_status == RebuildStatus::Building {
+                                            sender.input(RebuildInput::SetStatus(RebuildStatus::Success));
+                                        }
+                                    } else {
+                                        warn!("Rebuild failed with status {}", status);
+                                        sender.input(RebuildInput::SetStatus(RebuildStatus::Error));
                                     }
-                                } else {
-                                    warn!("Rebuild failed with status {}", status);
-                                    sender.input(RebuildInput::SetStatus(RebuildStatus::Error));
                                 }
                             }
                         }
@@ -150,6 +214,19 @@ impl SimpleComponent for RebuildModel {
                         connect_clicked[sender] => move |_| {
                             sender.input(RebuildInput::Close);
                         }
+                    },
+                    // A failed switch may leave the new generation partially activated
+                    gtk::Button {
+                        #[track(model.changed(RebuildModel::status()))]
+                        set_visible: model.status == RebuildStatus::Error && model.mode == RebuildMode::Switch && !model.rollback,
+                        add_css_class: "flat",
+                        add_css_class: "destructive-action",
+                        set_hexpand: true,
+                        set_label: "Roll Back",
+                        set_tooltip_text: Some("Switch back to the previous generation"),
+                        connect_clicked[sender] => move |_| {
+                            sender.input(RebuildInput::Rollback(None));
+                        }
                     }
                 }
             }
@@ -168,9 +245,19 @@ impl SimpleComponent for RebuildModel {
             flakepath: init.flakepath,
             modulepath: init.modulepath,
             generations: init.generations,
+            mode: RebuildMode::default(),
+            rollback: false,
+            secrets: vec![],
+            progress: None,
+            poll: None,
+            progressbar: gtk::ProgressBar::new(),
+            pulsing: false,
+            phase: String::new(),
+            error: None,
             tracker: 0,
         };
         let terminal = &model.terminal;
+        let progressbar = &model.progressbar;
         let widgets = view_output!();
         ComponentParts { model, widgets }
     }
@@ -178,39 +265,115 @@ impl SimpleComponent for RebuildModel {
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
         self.reset();
         match message {
-            RebuildInput::Rebuild(modified_config, moduleconfig) => {
+            RebuildInput::Rebuild(modified_config, moduleconfig, modified_modules, mode) => {
+                self.set_mode(mode);
+                self.set_rollback(false);
+                self.set_visible(true);
+                sender.input(RebuildInput::SetStatus(RebuildStatus::Building));
+                let output = match applychanges(&moduleconfig, &modified_config) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        warn!("{:#}", e);
+                        sender.input(RebuildInput::Fail(format!("{:#}", e)));
+                        return;
+                    }
+                };
+                let mut secrets = vec![];
+                for (attribute, value) in &modified_config {
+                    if let PendingChange::Set(ModuleOption::Secret { secret: Some(secret), .. }) = value {
+                        match StagedSecret::new(secret) {
+                            Ok(staged) => secrets.push((attribute.to_string(), staged)),
+                            Err(e) => {
+                                warn!("Failed to stage secret for {}: {}", attribute, e);
+                                sender.input(RebuildInput::Fail(format!("Failed to stage secret for {}: {}", attribute, e)));
+                                return;
+                            }
+                        }
+                    }
+                }
+                let mut args = vec![
+                    String::from("/usr/bin/env"),
+                    String::from("pkexec"),
+                    format!("{}/smm-helper", LIBEXECDIR),
+                    String::from("write-rebuild"),
+                ];
+                args.extend(self.followprogress(&sender));
+                args.extend([
+                    String::from("--content"),
+                    output,
+                    String::from("--path"),
+                    self.modulepath.to_string_lossy().to_string(),
+                ]);
+                if !modified_modules.is_empty() {
+                    match fs::read_to_string(&self.flakepath)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|flake| setmodulesinstalled(&flake, &modified_modules))
+                    {
+                        Ok(flake) => {
+                            args.push(String::from("--content"));
+                            args.push(flake);
+                            args.push(String::from("--path"));
+                            args.push(self.flakepath.to_string_lossy().to_string());
+                        }
+                        Err(e) => {
+                            warn!("Failed to update flake modules: {}", e);
+                            sender.input(RebuildInput::Fail(format!("Failed to update flake modules: {}", e)));
+                            return;
+                        }
+                    }
+                }
+                for (attribute, staged) in &secrets {
+                    args.push(String::from("--secret"));
+                    args.push(attribute.to_string());
+                    args.push(String::from("--secret-file"));
+                    args.push(staged.path().to_string_lossy().to_string());
+                }
+                self.secrets = secrets.into_iter().map(|(_, staged)| staged).collect();
+                if mode == RebuildMode::Build {
+                    args.push(String::from("--restore"));
+                }
+                args.push(String::from("--"));
+                args.push(mode.command().to_string());
+                args.push(String::from("--flake"));
+                args.push(self.flakepath.to_string_lossy().to_string());
+                self.terminal.spawn_async(
+                    vte::PtyFlags::DEFAULT,
+                    Some("/"),
+                    &args.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                    &[],
+                    glib::SpawnFlags::DEFAULT,
+                    || (),
+                    -1,
+                    gio::Cancellable::NONE,
+                    Self::spawned(&sender),
+                );
+            }
+            RebuildInput::Rollback(generation) => {
+                self.set_rollback(true);
                 self.set_visible(true);
                 sender.input(RebuildInput::SetStatus(RebuildStatus::Building));
-                let mut output = moduleconfig;
-                for (attribute, value) in modified_config {
-                    output = nix_editor::write::write(&output, &attribute, &value.value())
-                        .unwrap()
-                        .to_string();
+                self.terminal.reset(true, true);
+                let mut args = vec![
+                    String::from("/usr/bin/env"),
+                    String::from("pkexec"),
+                    format!("{}/smm-helper", LIBEXECDIR),
+                    String::from("rollback"),
+                ];
+                args.extend(self.followprogress(&sender));
+                if let Some(generation) = generation {
+                    args.push(String::from("--generation"));
+                    args.push(generation.to_string());
                 }
-                output = nixpkgs_fmt::reformat_string(&output);
                 self.terminal.spawn_async(
                     vte::PtyFlags::DEFAULT,
                     Some("/"),
-                    &[
-                        "/usr/bin/env",
-                        "pkexec",
-                        &format!("{}/smm-helper", LIBEXECDIR),
-                        "write-rebuild",
-                        "--content",
-                        &output,
-                        "--path",
-                        &self.modulepath.to_string_lossy(),
-                        "--",
-                        "switch",
-                        "--flake",
-                        &self.flakepath.to_string_lossy(),
-                    ],
+                    &args.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
                     &[],
                     glib::SpawnFlags::DEFAULT,
                     || (),
                     -1,
                     gio::Cancellable::NONE,
-                    |_| (),
+                    Self::spawned(&sender),
                 );
             }
             RebuildInput::Close => {
@@ -224,14 +387,98 @@ impl SimpleComponent for RebuildModel {
                     || (),
                     -1,
                     gio::Cancellable::NONE,
-                    |_| (),
+                    Self::spawned(&sender),
                 );
                 self.set_visible(false);
-                let _ = sender.output(AppInput::Reload);
+                // A test build restores modules.nix, so the changes are still pending
+                if self.rollback || self.mode != RebuildMode::Build {
+                    let _ = sender.output(AppInput::Reload);
+                }
             }
             RebuildInput::SetStatus(status) => {
+                if status == RebuildStatus::Building {
+                    self.set_phase(String::new());
+                    self.set_error(None);
+                    self.pulsing = true;
+                    self.progressbar.set_fraction(0.0);
+                } else {
+                    self.secrets.clear();
+                    self.readprogress();
+                    if let Some(poll) = self.poll.take() {
+                        poll.remove();
+                    }
+                    self.progress = None;
+                }
                 self.set_status(status);
             }
+            RebuildInput::Fail(message) => {
+                sender.input(RebuildInput::SetStatus(RebuildStatus::Error));
+                self.set_error(Some(message));
+            }
+            RebuildInput::Poll => self.readprogress(),
+        }
+    }
+}
+
+impl RebuildModel {
+    /// Create a file for the helper to report progress in and start reading it, returning the arguments that pass it on.
+    fn followprogress(&mut self, sender: &ComponentSender<Self>) -> Vec<String> {
+        if let Some(poll) = self.poll.take() {
+            poll.remove();
+        }
+        let progress = match ProgressFile::new() {
+            Ok(progress) => progress,
+            Err(e) => {
+                warn!("Not showing rebuild progress: {}", e);
+                return vec![];
+            }
+        };
+        let args = vec![
+            String::from("--progress"),
+            progress.path().to_string_lossy().to_string(),
+        ];
+        self.progress = Some(progress);
+        let sender = sender.clone();
+        self.poll = Some(glib::timeout_add_local(Duration::from_millis(200), move || {
+            sender.input(RebuildInput::Poll);
+            glib::Continue(true)
+        }));
+        args
+    }
+
+    /// Callback for a command started in the terminal, failing the rebuild if it could not be started.
+    fn spawned(sender: &ComponentSender<Self>) -> impl FnOnce(Result<glib::Pid, glib::Error>) + 'static {
+        let sender = sender.clone();
+        move |result| {
+            if let Err(e) = result {
+                sender.input(RebuildInput::Fail(format!("{}", e)));
+            }
+        }
+    }
+
+    fn readprogress(&mut self) {
+        let events = match &mut self.progress {
+            Some(progress) => progress.read(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                ProgressEvent::Phase { phase, steps: Some((done, total)) } => {
+                    self.set_phase(format!("{} ({} of {})", phase.label(), done, total));
+                    self.pulsing = false;
+                    self.progressbar
+                        .set_fraction(if total > 0 { done as f64 / total as f64 } else { 0.0 });
+                }
+                ProgressEvent::Phase { phase, steps: None } => {
+                    self.set_phase(phase.label().to_string());
+                    self.pulsing = true;
+                }
+                ProgressEvent::Error(message) => self.set_error(Some(message)),
+                ProgressEvent::Done => (),
+            }
+        }
+        if self.pulsing && self.status == RebuildStatus::Building {
+            self.progressbar.pulse();
         }
     }
 }
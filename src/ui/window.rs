@@ -1,15 +1,23 @@
 use super::{
+    catalog::page::{CatalogPageInit, CatalogPageInput, CatalogPageModel},
+    diagnostic_factory::{DiagnosticInit, DiagnosticModel},
     error_dialog::{ErrorDialogInput, ErrorDialogModel},
+    generations::page::{GenerationsPageInit, GenerationsPageInput, GenerationsPageModel},
+    history::History,
     load::{reload, ReloadOutput},
     module::page::{ModulePageInput, ModulePageModel},
     modulecard_factory::ModuleCardModel,
     rebuild::{
         confirm_dialog::ConfirmDialogModel,
         rebuild_dialog::{RebuildInput, RebuildModel},
+        RebuildMode,
     },
 };
 use crate::{
-    modules::{ModuleData, ModuleOption},
+    modules::{
+        generations::{generationconfig, generationflake, restorechanges, restoremodules},
+        roots::ModuleRoot, Module, ModuleOption, PendingChange,
+    },
     ui::{
         load::LoadOutput,
         module::page::ModulePageInit,
@@ -20,7 +28,7 @@ use crate::{
         }, about::AboutPageModel,
     },
 };
-use adw::{gtk, prelude::*};
+use adw::{gio, gtk, prelude::*};
 use nix_data::config::configfile::NixDataConfig;
 use relm4::{
     adw, factory::FactoryVecDeque, Component, ComponentController, ComponentParts, ComponentSender,
@@ -31,7 +39,10 @@ use std::{collections::HashMap, convert::identity};
 pub struct AppModel {
     config: NixDataConfig,
     modulecardsfactory: FactoryVecDeque<ModuleCardModel>,
+    diagnosticsfactory: FactoryVecDeque<DiagnosticModel>,
     modulepage: Controller<ModulePageModel>,
+    catalogpage: Controller<CatalogPageModel>,
+    generationspage: Controller<GenerationsPageModel>,
     aboutpage: Controller<AboutPageModel>,
     main_leaflet: adw::Leaflet,
     main_box: gtk::Box,
@@ -41,18 +52,51 @@ pub struct AppModel {
     error_dialog: Controller<ErrorDialogModel>,
 
     moduleconfig: String,
+    moduleroots: Vec<ModuleRoot>,
+    modules: Vec<Module>,
+    available: Vec<Module>,
 
     current_config: HashMap<String, ModuleOption>,
-    modified_config: HashMap<String, ModuleOption>,
+    /// Pending changes to option values
+    modified_config: HashMap<String, PendingChange>,
+    /// Module shown in the module page
+    openmodule: Option<Module>,
+    /// Pending changes to the flake module list, keyed by `Module::flakeattr`
+    modified_modules: HashMap<String, bool>,
+    history: History<PendingState>,
+    /// Option changed by the last edit, so typing in one row is a single undo step
+    lastedit: Option<String>,
+    undo_action: gio::SimpleAction,
+    redo_action: gio::SimpleAction,
 }
 
+/// Pending option and module changes
+type PendingState = (HashMap<String, PendingChange>, HashMap<String, bool>);
+
 #[derive(Debug)]
 pub enum AppInput {
-    OpenModulePage(ModuleData),
+    OpenModulePage(Module),
     CloseModulePage,
+    OpenCatalogPage,
+    CloseCatalogPage,
+    OpenGenerationsPage,
+    CloseGenerationsPage,
+    /// Make the module settings of a generation the pending changes and apply them
+    RestoreGeneration(u32),
     SetModuleOption(String, ModuleOption),
+    ResetModuleOption(String),
+    /// Drop the pending change of an option while its entered value is invalid
+    ClearModuleOption(String),
+    SetModuleInstalled(String, bool),
+    Undo,
+    Redo,
+    DiscardChanges,
+    /// The window is being closed, ask first if there are unapplied changes
+    Quit,
     ApplyChanges,
-    Rebuild,
+    Rebuild(RebuildMode),
+    /// Ask to switch the system back to the previous generation
+    Rollback,
     Reload,
 }
 
@@ -74,6 +118,10 @@ impl SimpleComponent for AppModel {
         #[name(main_window)]
         adw::ApplicationWindow {
             set_default_size: (800, 600),
+            connect_close_request[sender] => move |_| {
+                sender.input(AppInput::Quit);
+                gtk::Inhibit(true)
+            },
             #[local_ref]
             main_leaflet -> adw::Leaflet {
                 set_can_unfold: false,
@@ -91,15 +139,44 @@ impl SimpleComponent for AppModel {
                         set_title_widget = &gtk::Label {
                             set_label: "SnowflakeOS Module Manager"
                         },
+                        pack_start = &gtk::Button {
+                            add_css_class: "flat",
+                            set_icon_name: "view-grid-symbolic",
+                            set_tooltip_text: Some("Module Catalog"),
+                            connect_clicked[sender] => move |_| {
+                                sender.input(AppInput::OpenCatalogPage)
+                            }
+                        },
+                        pack_start = &gtk::Button {
+                            add_css_class: "flat",
+                            set_icon_name: "edit-undo-symbolic",
+                            set_tooltip_text: Some("Undo"),
+                            set_action_name: Some("edit.undo"),
+                        },
+                        pack_start = &gtk::Button {
+                            add_css_class: "flat",
+                            set_icon_name: "edit-redo-symbolic",
+                            set_tooltip_text: Some("Redo"),
+                            set_action_name: Some("edit.redo"),
+                        },
                         pack_end = &gtk::Button {
                             #[watch]
-                            set_visible: !model.modified_config.is_empty(),
+                            set_visible: model.has_changes(),
                             add_css_class: "suggested-action",
                             set_label: "Apply",
                             connect_clicked[sender] => move |_| {
                                 sender.input(AppInput::ApplyChanges)
                             }
                         },
+                        pack_end = &gtk::Button {
+                            #[watch]
+                            set_visible: model.has_changes(),
+                            set_label: "Discard",
+                            set_tooltip_text: Some("Discard changes"),
+                            connect_clicked[sender] => move |_| {
+                                sender.input(AppInput::DiscardChanges)
+                            }
+                        },
                         pack_end: menu = &gtk::MenuButton {
                             add_css_class: "flat",
                             set_icon_name: "open-menu-symbolic",
@@ -114,16 +191,29 @@ impl SimpleComponent for AppModel {
                         set_halign: gtk::Align::Fill,
                         set_valign: gtk::Align::Fill,
                         adw::Clamp {
-                            #[local_ref]
-                            modulecardsbox -> gtk::Box {
+                            gtk::Box {
                                 set_orientation: gtk::Orientation::Vertical,
-                                set_spacing: 15,
-                                set_margin_all: 15,
+                                #[local_ref]
+                                modulecardsbox -> gtk::Box {
+                                    set_orientation: gtk::Orientation::Vertical,
+                                    set_spacing: 15,
+                                    set_margin_all: 15,
+                                },
+                                #[local_ref]
+                                diagnosticsgroup -> adw::PreferencesGroup {
+                                    #[watch]
+                                    set_visible: !model.diagnosticsfactory.is_empty(),
+                                    set_margin_all: 15,
+                                    set_title: "Modules with errors",
+                                    set_description: Some("These modules could not be loaded"),
+                                }
                             }
                         }
                     }
                 },
                 append = model.modulepage.widget(),
+                append = model.catalogpage.widget(),
+                append = model.generationspage.widget(),
             }
 
         }
@@ -131,6 +221,8 @@ impl SimpleComponent for AppModel {
 
     menu! {
         mainmenu: {
+            "Generations" => GenerationsAction,
+            "Roll Back System" => RollbackAction,
             "About" => AboutAction,
         }
     }
@@ -145,12 +237,18 @@ impl SimpleComponent for AppModel {
             sender.input_sender(),
         );
 
+        let mut diagnosticsfactory =
+            FactoryVecDeque::new(adw::PreferencesGroup::new(), sender.input_sender());
+
         let LoadOutput {
             config,
             moduleconfig,
-            modulepath,
+            modulefile,
             flakepath,
             modules,
+            available,
+            moduleroots,
+            diagnostics,
             current_config,
         } = init.load;
 
@@ -162,18 +260,33 @@ impl SimpleComponent for AppModel {
         }
         modulecardsfactory_guard.drop();
 
+        let mut diagnosticsfactory_guard = diagnosticsfactory.guard();
+        for diagnostic in diagnostics {
+            diagnosticsfactory_guard.push_back(DiagnosticInit { diagnostic });
+        }
+        diagnosticsfactory_guard.drop();
+
         let modulepage = ModulePageModel::builder()
             .launch(ModulePageInit {})
             .forward(sender.input_sender(), identity);
+        let catalogpage = CatalogPageModel::builder()
+            .launch(CatalogPageInit {})
+            .forward(sender.input_sender(), identity);
+        let generationspage = GenerationsPageModel::builder()
+            .launch(GenerationsPageInit {})
+            .forward(sender.input_sender(), identity);
         let confirm_dialog = ConfirmDialogModel::builder()
             .transient_for(root)
-            .launch(ConfirmDialogInit { modules })
+            .launch(ConfirmDialogInit {
+                modules: [modules.clone(), available.clone()].concat(),
+                modulepath: modulefile.path().to_string_lossy().to_string(),
+            })
             .forward(sender.input_sender(), identity);
         let rebuild_dialog = RebuildModel::builder()
             .transient_for(root)
             .launch(RebuildInit {
                 flakepath,
-                modulepath,
+                modulepath: modulefile.path().to_path_buf(),
                 generations: config.generations,
             })
             .forward(sender.input_sender(), identity);
@@ -185,21 +298,45 @@ impl SimpleComponent for AppModel {
             .launch(root.clone().upcast())
             .detach();
 
+        let undo: RelmAction<UndoAction> = {
+            let sender = sender.clone();
+            RelmAction::new_stateless(move |_| sender.input(AppInput::Undo))
+        };
+        let redo: RelmAction<RedoAction> = {
+            let sender = sender.clone();
+            RelmAction::new_stateless(move |_| sender.input(AppInput::Redo))
+        };
+        undo.gio_action().set_enabled(false);
+        redo.gio_action().set_enabled(false);
+
         let model = AppModel {
             config,
             modulecardsfactory,
+            diagnosticsfactory,
             modulepage,
+            catalogpage,
+            generationspage,
             aboutpage,
             main_leaflet: adw::Leaflet::new(),
             main_box: gtk::Box::new(gtk::Orientation::Vertical, 0),
             moduleconfig,
+            moduleroots,
+            modules,
+            available,
             confirm_dialog,
             rebuild_dialog,
             error_dialog,
             current_config,
             modified_config: HashMap::new(),
+            openmodule: None,
+            modified_modules: HashMap::new(),
+            history: History::default(),
+            lastedit: None,
+            undo_action: undo.gio_action().clone(),
+            redo_action: redo.gio_action().clone(),
         };
         let modulecardsbox = model.modulecardsfactory.widget();
+        let diagnosticsgroup = model.diagnosticsfactory.widget();
         let main_leaflet = &model.main_leaflet;
         let main_box = &model.main_box;
         
@@ -212,21 +349,43 @@ impl SimpleComponent for AppModel {
                 sender.send(()).unwrap();
             })
         };
+        let rollback: RelmAction<RollbackAction> = {
+            let sender = sender.clone();
+            RelmAction::new_stateless(move |_| sender.input(AppInput::Rollback))
+        };
+        let generations: RelmAction<GenerationsAction> = {
+            let sender = sender.clone();
+            RelmAction::new_stateless(move |_| sender.input(AppInput::OpenGenerationsPage))
+        };
+        group.add_action(generations);
+        group.add_action(rollback);
         group.add_action(aboutpage);
         let actions = group.into_action_group();
         widgets
             .main_window
             .insert_action_group("menu", Some(&actions));
 
+        let mut group = RelmActionGroup::<EditActionGroup>::new();
+        group.add_action(undo);
+        group.add_action(redo);
+        let actions = group.into_action_group();
+        widgets
+            .main_window
+            .insert_action_group("edit", Some(&actions));
+        let app = relm4::main_application();
+        app.set_accels_for_action("edit.undo", &["<primary>z"]);
+        app.set_accels_for_action("edit.redo", &["<primary><shift>z"]);
+
 
         ComponentParts { model, widgets }
     }
 
     fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>) {
         match message {
-            AppInput::OpenModulePage(data) => {
+            AppInput::OpenModulePage(module) => {
+                self.openmodule = Some(module.clone());
                 self.modulepage.emit(ModulePageInput::OpenModulePage(
-                    data,
+                    module,
                     self.current_config.clone(),
                     self.modified_config.clone(),
                 ));
@@ -234,36 +393,249 @@ impl SimpleComponent for AppModel {
                     .set_visible_child(self.modulepage.widget());
             }
             AppInput::CloseModulePage => {
+                self.openmodule = None;
                 self.main_leaflet.set_visible_child(&self.main_box);
             }
+            AppInput::OpenCatalogPage => {
+                self.catalogpage.emit(CatalogPageInput::OpenCatalogPage(
+                    self.modules.clone(),
+                    self.available.clone(),
+                    self.modified_modules.clone(),
+                ));
+                self.main_leaflet
+                    .set_visible_child(self.catalogpage.widget());
+            }
+            AppInput::CloseCatalogPage => {
+                self.main_leaflet.set_visible_child(&self.main_box);
+            }
+            AppInput::OpenGenerationsPage => {
+                self.generationspage
+                    .emit(GenerationsPageInput::OpenGenerationsPage);
+                self.main_leaflet
+                    .set_visible_child(self.generationspage.widget());
+            }
+            AppInput::CloseGenerationsPage => {
+                self.main_leaflet.set_visible_child(&self.main_box);
+            }
+            AppInput::RestoreGeneration(number) => {
+                let moduleconfig = match generationconfig(number) {
+                    Some(moduleconfig) => moduleconfig,
+                    None => {
+                        self.error_dialog.emit(ErrorDialogInput::Show(
+                            format!("Failed to restore generation {}", number),
+                            "The module settings of this generation were not saved".to_string(),
+                        ));
+                        return;
+                    }
+                };
+                let changes = restorechanges(
+                    &self.current_config,
+                    &moduleconfig,
+                    &[self.modules.clone(), self.available.clone()].concat(),
+                );
+                let modules = match generationflake(number)
+                    .map(|flake| restoremodules(&self.modules, &self.available, &flake))
+                {
+                    Some(Ok(modules)) => modules,
+                    Some(Err(e)) => {
+                        self.error_dialog.emit(ErrorDialogInput::Show(
+                            format!("Failed to restore generation {}", number),
+                            format!("Failed to read its installed modules: {}", e),
+                        ));
+                        return;
+                    }
+                    // Only the module settings of this generation were saved
+                    None => HashMap::new(),
+                };
+                if changes.is_empty() && modules.is_empty() {
+                    self.error_dialog.emit(ErrorDialogInput::Show(
+                        format!("Nothing to restore from generation {}", number),
+                        "Its module settings are the same as the current ones".to_string(),
+                    ));
+                    return;
+                }
+                self.record();
+                self.lastedit = None;
+                self.modified_config = changes;
+                self.modified_modules = modules;
+                self.refresh();
+                self.confirm_dialog.emit(ConfirmDialogInput::Open(
+                    self.current_config.clone(),
+                    self.modified_config.clone(),
+                    self.modified_modules.clone(),
+                    self.moduleconfig.clone(),
+                ));
+            }
             AppInput::SetModuleOption(id, value) => {
-                // TODO: Decied what to do about default values. Allow user to deref/set to default?
-                // Or always save config once set once?
+                if self.lastedit.as_ref() != Some(&id) {
+                    self.record();
+                    self.lastedit = Some(id.to_string());
+                }
                 if self.current_config.get(&id) == Some(&value) {
                     self.modified_config.remove(&id);
                 } else {
-                    self.modified_config.insert(id, value);
+                    self.modified_config.insert(id, PendingChange::Set(value));
+                }
+                self.show_apply();
+            }
+            AppInput::ResetModuleOption(id) => {
+                self.record();
+                self.lastedit = None;
+                if self.current_config.contains_key(&id) {
+                    self.modified_config.insert(id, PendingChange::Unset);
+                } else {
+                    self.modified_config.remove(&id);
+                }
+                // Show the default value in the option row
+                self.refresh();
+            }
+            AppInput::ClearModuleOption(id) => {
+                if self.modified_config.contains_key(&id) {
+                    if self.lastedit.as_ref() != Some(&id) {
+                        self.record();
+                        self.lastedit = Some(id.to_string());
+                    }
+                    // The row keeps showing the invalid input, so it is not refreshed
+                    self.modified_config.remove(&id);
+                    self.show_apply();
+                }
+            }
+            AppInput::SetModuleInstalled(flakeattr, install) => {
+                self.record();
+                self.lastedit = None;
+                let installed = self.modules.iter().any(|x| x.flakeattr == flakeattr);
+                // Options of removed modules no longer exist, so they are unset as well
+                let options = self
+                    .modules
+                    .iter()
+                    .filter(|x| x.flakeattr == flakeattr)
+                    .flat_map(|x| x.config.options.iter())
+                    .filter(|x| self.current_config.contains_key(&x.id))
+                    .map(|x| x.id.to_string())
+                    .collect::<Vec<_>>();
+                if installed == install {
+                    self.modified_modules.remove(&flakeattr);
+                    for id in options {
+                        if self.modified_config.get(&id) == Some(&PendingChange::Unset) {
+                            self.modified_config.remove(&id);
+                        }
+                    }
+                } else {
+                    self.modified_modules.insert(flakeattr, install);
+                    if !install {
+                        for id in options {
+                            self.modified_config.insert(id, PendingChange::Unset);
+                        }
+                    }
+                }
+                self.show_apply();
+            }
+            AppInput::Undo => {
+                let current = (self.modified_config.clone(), self.modified_modules.clone());
+                if let Some((config, modules)) = self.history.undo(current) {
+                    self.modified_config = config;
+                    self.modified_modules = modules;
+                    self.lastedit = None;
+                    self.refresh();
                 }
-                self.modulepage
-                    .emit(ModulePageInput::ShowApply(!self.modified_config.is_empty()))
+            }
+            AppInput::Redo => {
+                let current = (self.modified_config.clone(), self.modified_modules.clone());
+                if let Some((config, modules)) = self.history.redo(current) {
+                    self.modified_config = config;
+                    self.modified_modules = modules;
+                    self.lastedit = None;
+                    self.refresh();
+                }
+            }
+            AppInput::DiscardChanges => {
+                self.record();
+                self.lastedit = None;
+                self.modified_config.clear();
+                self.modified_modules.clear();
+                self.refresh();
+            }
+            AppInput::Quit => {
+                if !self.has_changes() {
+                    relm4::main_application().quit();
+                    return;
+                }
+                let dialog = adw::MessageDialog::builder()
+                    .heading("Discard changes?")
+                    .body("Some changes have not been applied yet and will be lost.")
+                    .modal(true)
+                    .build();
+                if let Some(window) = self
+                    .main_leaflet
+                    .root()
+                    .and_then(|x| x.downcast::<gtk::Window>().ok())
+                {
+                    dialog.set_transient_for(Some(&window));
+                }
+                dialog.add_response("cancel", "Cancel");
+                dialog.add_response("discard", "Discard and Quit");
+                dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+                dialog.set_default_response(Some("cancel"));
+                dialog.set_close_response("cancel");
+                dialog.connect_response(None, |_, response| {
+                    if response == "discard" {
+                        relm4::main_application().quit();
+                    }
+                });
+                dialog.present();
             }
             AppInput::ApplyChanges => self.confirm_dialog.emit(ConfirmDialogInput::Open(
                 self.current_config.clone(),
                 self.modified_config.clone(),
+                self.modified_modules.clone(),
+                self.moduleconfig.clone(),
             )),
-            AppInput::Rebuild => self.rebuild_dialog.emit(RebuildInput::Rebuild(
+            AppInput::Rebuild(mode) => self.rebuild_dialog.emit(RebuildInput::Rebuild(
                 self.modified_config.clone(),
                 self.moduleconfig.clone(),
+                self.modified_modules.clone(),
+                mode,
             )),
-            AppInput::Reload => match reload(&self.config) {
+            AppInput::Rollback => {
+                let dialog = adw::MessageDialog::builder()
+                    .heading("Roll back system?")
+                    .body("The system will switch back to the previous generation. Your module configuration is not changed.")
+                    .modal(true)
+                    .build();
+                if let Some(window) = self
+                    .main_leaflet
+                    .root()
+                    .and_then(|x| x.downcast::<gtk::Window>().ok())
+                {
+                    dialog.set_transient_for(Some(&window));
+                }
+                dialog.add_response("cancel", "Cancel");
+                dialog.add_response("rollback", "Roll Back");
+                dialog.set_response_appearance("rollback", adw::ResponseAppearance::Destructive);
+                dialog.set_default_response(Some("cancel"));
+                dialog.set_close_response("cancel");
+                let rebuild_sender = self.rebuild_dialog.sender().clone();
+                dialog.connect_response(None, move |_, response| {
+                    if response == "rollback" {
+                        rebuild_sender.emit(RebuildInput::Rollback(None));
+                    }
+                });
+                dialog.present();
+            }
+            AppInput::Reload => match reload(&self.config, &self.moduleroots) {
                 Ok(ReloadOutput {
                     modules,
+                    available,
                     current_config,
                     moduleconfig,
+                    diagnostics,
                 }) => {
                     self.current_config = current_config;
                     self.moduleconfig = moduleconfig;
                     self.modified_config.clear();
+                    self.modified_modules.clear();
+                    self.history.clear();
+                    self.lastedit = None;
                     let mut modulecardsfactory_guard = self.modulecardsfactory.guard();
                     modulecardsfactory_guard.clear();
                     for module in modules.iter() {
@@ -272,9 +644,19 @@ impl SimpleComponent for AppModel {
                         });
                     }
                     modulecardsfactory_guard.drop();
+                    let mut diagnosticsfactory_guard = self.diagnosticsfactory.guard();
+                    diagnosticsfactory_guard.clear();
+                    for diagnostic in diagnostics {
+                        diagnosticsfactory_guard.push_back(DiagnosticInit { diagnostic });
+                    }
+                    diagnosticsfactory_guard.drop();
+                    self.confirm_dialog.emit(ConfirmDialogInput::SetModules(
+                        [modules.clone(), available.clone()].concat(),
+                    ));
+                    self.modules = modules;
+                    self.available = available;
                     self.main_leaflet.set_visible_child(&self.main_box);
-                    self.modulepage
-                        .emit(ModulePageInput::ShowApply(false));
+                    self.show_apply();
                 }
                 Err(e) => {
                     self.error_dialog.emit(ErrorDialogInput::Show(
@@ -287,5 +669,49 @@ impl SimpleComponent for AppModel {
     }
 }
 
+impl AppModel {
+    fn has_changes(&self) -> bool {
+        !self.modified_config.is_empty() || !self.modified_modules.is_empty()
+    }
+
+    fn show_apply(&self) {
+        self.modulepage
+            .emit(ModulePageInput::ShowApply(self.has_changes()));
+        self.catalogpage
+            .emit(CatalogPageInput::ShowApply(self.has_changes()));
+        self.undo_action.set_enabled(self.history.can_undo());
+        self.redo_action.set_enabled(self.history.can_redo());
+    }
+
+    /// Save the pending changes before they are modified, so the modification can be undone.
+    fn record(&mut self) {
+        self.history
+            .record((self.modified_config.clone(), self.modified_modules.clone()));
+    }
+
+    /// Show the pending changes in the open pages after they were replaced.
+    fn refresh(&self) {
+        if let Some(module) = &self.openmodule {
+            self.modulepage.emit(ModulePageInput::OpenModulePage(
+                module.clone(),
+                self.current_config.clone(),
+                self.modified_config.clone(),
+            ));
+        }
+        self.catalogpage.emit(CatalogPageInput::OpenCatalogPage(
+            self.modules.clone(),
+            self.available.clone(),
+            self.modified_modules.clone(),
+        ));
+        self.show_apply();
+    }
+}
+
 relm4::new_action_group!(MenuActionGroup, "menu");
 relm4::new_stateless_action!(AboutAction, MenuActionGroup, "about");
+relm4::new_stateless_action!(RollbackAction, MenuActionGroup, "rollback");
+relm4::new_stateless_action!(GenerationsAction, MenuActionGroup, "generations");
+
+relm4::new_action_group!(EditActionGroup, "edit");
+relm4::new_stateless_action!(UndoAction, EditActionGroup, "undo");
+relm4::new_stateless_action!(RedoAction, EditActionGroup, "redo");
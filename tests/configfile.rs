@@ -0,0 +1,103 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use proptest::prelude::*;
+use snowflakeos_module_manager::modules::{
+    configfile::applychanges,
+    load::readoptions,
+    roots::{ModuleRoot, ModuleRootKind},
+    Module, ModuleData, ModuleOption, OptionData, OptionType, PendingChange,
+};
+
+const MODULES_NIX: &str = "{ ... }:\n{\n}\n";
+
+/// A module with a single option of the given type.
+fn module(id: &str, op_type: OptionType) -> Module {
+    Module {
+        name: String::from("test"),
+        path: PathBuf::from("/modules/test"),
+        root: ModuleRoot::new("/modules", ModuleRootKind::System),
+        flakeattr: String::from("test.nixosModules.test"),
+        config: ModuleData {
+            name: String::from("Test"),
+            id: String::from("test"),
+            flake: String::from("test"),
+            description: None,
+            version: String::from("1.0"),
+            options: vec![OptionData {
+                label: String::from("Option"),
+                id: id.to_string(),
+                description: None,
+                op_type,
+            }],
+            icon: None,
+        },
+    }
+}
+
+/// Apply the change to an empty `modules.nix` and read the options back from the result.
+fn roundtrip(module: Module, option: ModuleOption) -> HashMap<String, ModuleOption> {
+    let id = module.config.options[0].id.to_string();
+    let output = applychanges(MODULES_NIX, &HashMap::from([(id, PendingChange::Set(option))]))
+        .expect("changes apply");
+    readoptions(&output, &[module])
+}
+
+proptest! {
+    #[test]
+    fn text_options_round_trip_through_modules_nix(value in any::<String>()) {
+        let module = module(
+            "snowflakeos.test.text",
+            OptionType::Text {
+                default: String::new(),
+                pattern: None,
+                min_length: None,
+                max_length: None,
+                placeholder: None,
+            },
+        );
+        let option = ModuleOption::Text { value };
+        let options = roundtrip(module, option.clone());
+        prop_assert_eq!(options.get("snowflakeos.test.text"), Some(&option));
+    }
+
+    #[test]
+    fn string_list_options_round_trip_through_modules_nix(
+        value in proptest::collection::vec(any::<String>(), 0..5)
+    ) {
+        let module = module("snowflakeos.test.list", OptionType::StringList { default: vec![] });
+        let option = ModuleOption::StringList { value };
+        let options = roundtrip(module, option.clone());
+        prop_assert_eq!(options.get("snowflakeos.test.list"), Some(&option));
+    }
+}
+
+#[test]
+fn empty_string_lists_round_trip() {
+    let module = module("snowflakeos.test.list", OptionType::StringList { default: vec![] });
+    let option = ModuleOption::StringList { value: vec![] };
+    assert_eq!(option.value(), "[  ]");
+    assert_eq!(roundtrip(module, option.clone()).get("snowflakeos.test.list"), Some(&option));
+}
+
+#[test]
+fn maps_set_per_entry_are_not_replaced() {
+    let moduleconfig = "{ ... }:\n{\n  environment = {\n    variables.\"MY VAR\" = \"x\";\n  };\n}\n";
+    let changes = HashMap::from([(
+        String::from("environment.variables"),
+        PendingChange::Set(ModuleOption::Map { value: Default::default() }),
+    )]);
+    let err = applychanges(moduleconfig, &changes).unwrap_err();
+    assert!(err.to_string().contains("environment.variables.MY VAR"), "{}", err);
+    let changes = HashMap::from([(String::from("environment.variables"), PendingChange::Unset)]);
+    assert!(applychanges(moduleconfig, &changes).is_err());
+}
+
+#[test]
+fn maps_set_as_a_whole_are_replaced() {
+    let moduleconfig = "{ ... }:\n{\n  environment.variables = { FOO = \"x\"; };\n}\n";
+    let changes = HashMap::from([(
+        String::from("environment.variables"),
+        PendingChange::Set(ModuleOption::Map { value: Default::default() }),
+    )]);
+    assert!(applychanges(moduleconfig, &changes).is_ok());
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use snowflakeos_module_manager::modules::{
+    generations::{parsegenerations, restorechanges, restoremodules, Generation},
+    roots::{ModuleRoot, ModuleRootKind},
+    Module, ModuleData, ModuleOption, OptionData, OptionType, PendingChange,
+};
+
+fn module(flakeattr: &str, options: &[(&str, OptionType)]) -> Module {
+    Module {
+        name: flakeattr.to_string(),
+        path: PathBuf::from("/modules").join(flakeattr),
+        root: ModuleRoot::new("/modules", ModuleRootKind::System),
+        flakeattr: flakeattr.to_string(),
+        config: ModuleData {
+            name: flakeattr.to_string(),
+            id: flakeattr.to_string(),
+            flake: String::from("test"),
+            description: None,
+            version: String::from("1.0"),
+            options: options
+                .iter()
+                .map(|(id, op_type)| OptionData {
+                    label: id.to_string(),
+                    id: id.to_string(),
+                    description: None,
+                    op_type: op_type.clone(),
+                })
+                .collect(),
+            icon: None,
+        },
+    }
+}
+
+fn text() -> OptionType {
+    OptionType::Text {
+        default: String::new(),
+        pattern: None,
+        min_length: None,
+        max_length: None,
+        placeholder: None,
+    }
+}
+
+#[test]
+fn generations_are_listed_newest_first() {
+    let output = "41\t1700000000\t23.05.1\t\n43\t1700000200\t23.11\tcurrent\n42\t1700000100\t23.05.2\t\n";
+    assert_eq!(
+        parsegenerations(output),
+        vec![
+            Generation {
+                number: 43,
+                date: 1700000200,
+                version: String::from("23.11"),
+                current: true,
+            },
+            Generation {
+                number: 42,
+                date: 1700000100,
+                version: String::from("23.05.2"),
+                current: false,
+            },
+            Generation {
+                number: 41,
+                date: 1700000000,
+                version: String::from("23.05.1"),
+                current: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn generations_without_version_or_current_field_are_parsed() {
+    assert_eq!(
+        parsegenerations("7\t1700000000"),
+        vec![Generation {
+            number: 7,
+            date: 1700000000,
+            version: String::new(),
+            current: false,
+        }]
+    );
+}
+
+#[test]
+fn malformed_generation_lines_are_skipped() {
+    let output = "\nx\t1700000000\t23.05\t\n8\tyesterday\t23.05\t\n9\n10\t1700000000\t23.05\tcurrent\n";
+    let numbers = parsegenerations(output)
+        .iter()
+        .map(|x| x.number)
+        .collect::<Vec<_>>();
+    assert_eq!(numbers, vec![10]);
+}
+
+#[test]
+fn restoring_sets_changed_and_unsets_missing_options() {
+    let modules = [module(
+        "test.nixosModules.test",
+        &[
+            ("test.enable", OptionType::Switch { default: false }),
+            ("test.name", text()),
+            ("test.motd", text()),
+            ("test.greeting", text()),
+        ],
+    )];
+    let current = HashMap::from([
+        (String::from("test.enable"), ModuleOption::Switch { value: true }),
+        (String::from("test.name"), ModuleOption::Text { value: String::from("current") }),
+        (String::from("test.greeting"), ModuleOption::Text { value: String::from("hello") }),
+    ]);
+    let moduleconfig = r#"{ ... }:
+{
+  test.enable = false;
+  test.motd = "restored";
+  test.greeting = "hello";
+}
+"#;
+    assert_eq!(
+        restorechanges(&current, moduleconfig, &modules),
+        HashMap::from([
+            (
+                String::from("test.enable"),
+                PendingChange::Set(ModuleOption::Switch { value: false })
+            ),
+            (String::from("test.name"), PendingChange::Unset),
+            (
+                String::from("test.motd"),
+                PendingChange::Set(ModuleOption::Text { value: String::from("restored") })
+            ),
+        ])
+    );
+}
+
+#[test]
+fn restoring_the_same_settings_changes_nothing() {
+    let modules = [module(
+        "test.nixosModules.test",
+        &[("test.enable", OptionType::Switch { default: false })],
+    )];
+    let current = HashMap::from([(String::from("test.enable"), ModuleOption::Switch { value: true })]);
+    assert!(restorechanges(&current, "{ ... }:\n{\n  test.enable = true;\n}\n", &modules).is_empty());
+}
+
+#[test]
+fn restoring_modules_installs_and_removes_to_match_the_saved_flake() {
+    let installed = [module("a.nixosModules.kept", &[]), module("a.nixosModules.removed", &[])];
+    let available = [module("a.nixosModules.added", &[]), module("a.nixosModules.other", &[])];
+    let flake = r#"{
+  outputs = {
+    systems.modules.nixos = [
+      a.nixosModules.kept
+      a.nixosModules.added
+      a.nixosModules.deleted
+    ];
+  };
+}
+"#;
+    assert_eq!(
+        restoremodules(&installed, &available, flake).unwrap(),
+        HashMap::from([
+            (String::from("a.nixosModules.removed"), false),
+            (String::from("a.nixosModules.added"), true),
+        ])
+    );
+}
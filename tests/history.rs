@@ -0,0 +1,61 @@
+use snowflakeos_module_manager::ui::history::History;
+
+#[test]
+fn undo_and_redo_step_through_recorded_states() {
+    let mut history = History::default();
+    assert!(!history.can_undo() && !history.can_redo());
+    history.record(0);
+    history.record(1);
+    assert_eq!(history.undo(2), Some(1));
+    assert_eq!(history.undo(1), Some(0));
+    assert_eq!(history.undo(0), None);
+    assert!(history.can_redo());
+    assert_eq!(history.redo(0), Some(1));
+    assert_eq!(history.redo(1), Some(2));
+    assert_eq!(history.redo(2), None);
+    assert_eq!(history.undo(2), Some(1));
+}
+
+#[test]
+fn recording_after_undo_discards_redo() {
+    let mut history = History::default();
+    history.record(0);
+    history.record(1);
+    assert_eq!(history.undo(2), Some(1));
+    assert!(history.can_redo());
+    history.record(1);
+    assert!(!history.can_redo());
+    assert_eq!(history.redo(3), None);
+    assert_eq!(history.undo(3), Some(1));
+    assert_eq!(history.undo(1), Some(0));
+}
+
+#[test]
+fn oldest_states_are_dropped_beyond_the_limit() {
+    let mut history = History::new(3);
+    for state in 0..5 {
+        history.record(state);
+    }
+    assert_eq!(history.undo(5), Some(4));
+    assert_eq!(history.undo(4), Some(3));
+    assert_eq!(history.undo(3), Some(2));
+    assert_eq!(history.undo(2), None);
+    // Redoing fills the undo history up to the limit again
+    assert_eq!(history.redo(2), Some(3));
+    assert_eq!(history.redo(3), Some(4));
+    assert_eq!(history.redo(4), Some(5));
+    assert_eq!(history.undo(5), Some(4));
+    assert_eq!(history.undo(4), Some(3));
+    assert_eq!(history.undo(3), Some(2));
+    assert_eq!(history.undo(2), None);
+}
+
+#[test]
+fn clear_forgets_everything() {
+    let mut history = History::default();
+    history.record(0);
+    history.record(1);
+    history.undo(2);
+    history.clear();
+    assert!(!history.can_undo() && !history.can_redo());
+}
@@ -0,0 +1,95 @@
+use proptest::prelude::*;
+use snowflakeos_module_manager::modules::{
+    nixstring::{attrs, quote, unquote, unquote_attrs, unquote_list},
+    ModuleOption,
+};
+
+proptest! {
+    #[test]
+    fn quoted_text_round_trips(value in any::<String>()) {
+        prop_assert_eq!(unquote(&quote(&value)), Some(value));
+    }
+
+    #[test]
+    fn nix_special_characters_round_trip(value in r#"[a-z"\\$'{}\n\r\t ]*"#) {
+        prop_assert_eq!(unquote(&quote(&value)), Some(value));
+    }
+
+    #[test]
+    fn quoted_text_never_interpolates(value in r#"[a-z"\\${}]*"#) {
+        let quoted = quote(&value);
+        let inner = &quoted[1..quoted.len() - 1];
+        let mut escaped = false;
+        let mut chars = inner.chars().peekable();
+        while let Some(c) = chars.next() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else {
+                prop_assert!(c != '"', "unescaped quote in {}", quoted);
+                let interpolates = c == '$' && chars.peek() == Some(&'{');
+                prop_assert!(!interpolates, "interpolation in {}", quoted);
+            }
+        }
+    }
+
+    #[test]
+    fn text_option_value_round_trips(value in any::<String>()) {
+        let option = ModuleOption::Text { value: value.clone() };
+        prop_assert_eq!(unquote(&option.value()), Some(value));
+    }
+
+    #[test]
+    fn string_list_items_round_trip(values in proptest::collection::vec(any::<String>(), 0..5)) {
+        let option = ModuleOption::StringList { value: values.clone() };
+        prop_assert_eq!(unquote_list(&option.value()), Some(values));
+    }
+
+    #[test]
+    fn attribute_sets_round_trip(
+        entries in proptest::collection::vec((any::<String>(), any::<String>(), any::<i32>(), any::<bool>()), 0..5)
+    ) {
+        // Values are already encoded, as strings or plain literals
+        let entries = entries
+            .into_iter()
+            .map(|(name, text, number, literal)| {
+                (name, if literal { number.to_string() } else { quote(&text) })
+            })
+            .collect::<Vec<_>>();
+        let written = attrs(entries.iter().map(|(name, value)| (name.as_str(), value.clone())));
+        prop_assert_eq!(unquote_attrs(&written), Some(entries));
+    }
+}
+
+#[test]
+fn indented_strings_strip_indentation() {
+    assert_eq!(
+        unquote("''\n  first\n    second\n''"),
+        Some(String::from("first\n  second\n"))
+    );
+    assert_eq!(unquote("''\n  a\n  b''"), Some(String::from("a\nb")));
+    assert_eq!(unquote("''single''"), Some(String::from("single")));
+}
+
+#[test]
+fn indented_strings_unescape() {
+    assert_eq!(unquote("''''' '''''"), Some(String::from("'' ''")));
+    assert_eq!(unquote("''''${x}''"), Some(String::from("${x}")));
+    assert_eq!(unquote("''a''\\nb''"), Some(String::from("a\nb")));
+}
+
+#[test]
+fn interpolation_is_not_decoded() {
+    assert_eq!(unquote("\"${pkgs.hello}\""), None);
+    assert_eq!(unquote("''${pkgs.hello}''"), None);
+    assert_eq!(unquote("\"\\${literal}\""), Some(String::from("${literal}")));
+    assert_eq!(unquote("\"$${literal}\""), Some(String::from("$${literal}")));
+}
+
+#[test]
+fn unterminated_strings_are_rejected() {
+    assert_eq!(unquote("\"a\"b\""), None);
+    assert_eq!(unquote("\"a\\\""), None);
+    assert_eq!(unquote("plain"), None);
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use snowflakeos_module_manager::modules::{
+    nixstring,
+    roots::{ModuleRoot, ModuleRootKind},
+    secret::secretpath,
+    Module, ModuleData, OptionData, OptionType,
+};
+
+fn secretmodule() -> (Module, OptionData) {
+    let option = OptionData {
+        label: String::from("Password"),
+        id: String::from("test.password"),
+        description: None,
+        op_type: OptionType::Secret { placeholder: None },
+    };
+    let module = Module {
+        name: String::from("test"),
+        path: PathBuf::from("/modules/test"),
+        root: ModuleRoot::new("/modules", ModuleRootKind::System),
+        flakeattr: String::from("test.nixosModules.test"),
+        config: ModuleData {
+            name: String::from("Test"),
+            id: String::from("test"),
+            flake: String::from("test"),
+            description: None,
+            version: String::from("1.0"),
+            options: vec![option.clone()],
+            icon: None,
+        },
+    };
+    (module, option)
+}
+
+#[test]
+fn integer_steps_are_checked_across_the_whole_range() {
+    let option = OptionType::Integer {
+        default: 0,
+        min: Some(i64::MIN),
+        max: None,
+        step: Some(2),
+    };
+    assert!(option.check_integer(i64::MAX - 1).is_ok());
+    assert!(option.check_integer(i64::MAX).is_err());
+    let option = OptionType::Integer {
+        default: 0,
+        min: Some(-3),
+        max: Some(i64::MAX),
+        step: Some(i64::MAX),
+    };
+    assert!(option.check_integer(i64::MAX - 3).is_ok());
+    assert!(option.check_integer(i64::MAX).is_err());
+}
+
+#[test]
+fn secrets_must_point_to_their_own_file() {
+    let (module, option) = secretmodule();
+    let path = secretpath("test.password").to_string_lossy().to_string();
+    assert!(module.checkoption(&option, &nixstring::quote(&path)).is_ok());
+    for path in [
+        format!("{}/../../../../etc/shadow", path),
+        secretpath("test.other").to_string_lossy().to_string(),
+        String::from("/etc/shadow"),
+    ] {
+        assert!(module.checkoption(&option, &nixstring::quote(&path)).is_err(), "{}", path);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fs::OpenOptions, io::Write};
+
+use snowflakeos_module_manager::modules::progress::{Phase, ProgressEvent, ProgressFile};
+
+#[test]
+fn phases_are_parsed_with_and_without_steps() {
+    for (name, phase) in [
+        ("writing", Phase::Writing),
+        ("evaluating", Phase::Evaluating),
+        ("building", Phase::Building),
+        ("downloading", Phase::Downloading),
+        ("activating", Phase::Activating),
+        ("cleaning", Phase::Cleaning),
+    ] {
+        assert_eq!(ProgressEvent::parse(name), Some(ProgressEvent::Phase { phase, steps: None }));
+        assert_eq!(
+            ProgressEvent::parse(&format!("{}\t2\t5", name)),
+            Some(ProgressEvent::Phase { phase, steps: Some((2, 5)) })
+        );
+    }
+}
+
+#[test]
+fn errors_and_done_are_parsed() {
+    assert_eq!(
+        ProgressEvent::parse("error\tFailed to rebuild: exit code 1"),
+        Some(ProgressEvent::Error(String::from("Failed to rebuild: exit code 1")))
+    );
+    assert_eq!(ProgressEvent::parse("error"), Some(ProgressEvent::Error(String::new())));
+    assert_eq!(ProgressEvent::parse("done"), Some(ProgressEvent::Done));
+}
+
+#[test]
+fn malformed_lines_are_ignored() {
+    assert_eq!(ProgressEvent::parse(""), None);
+    assert_eq!(ProgressEvent::parse("compiling"), None);
+    assert_eq!(ProgressEvent::parse("Building"), None);
+    assert_eq!(ProgressEvent::parse("building\tone\t5"), None);
+    assert_eq!(ProgressEvent::parse("building\t1\t-5"), None);
+    assert_eq!(ProgressEvent::parse(" building"), None);
+}
+
+#[test]
+fn a_phase_with_only_finished_steps_has_no_steps() {
+    assert_eq!(
+        ProgressEvent::parse("building\t3"),
+        Some(ProgressEvent::Phase { phase: Phase::Building, steps: None })
+    );
+}
+
+#[test]
+fn events_are_read_as_complete_lines_arrive() {
+    let mut progress = ProgressFile::new().unwrap();
+    let mut writer = OpenOptions::new().append(true).open(progress.path()).unwrap();
+    assert_eq!(progress.read(), vec![]);
+    write!(writer, "writing\nbuild").unwrap();
+    assert_eq!(
+        progress.read(),
+        vec![ProgressEvent::Phase { phase: Phase::Writing, steps: None }]
+    );
+    write!(writer, "ing\t1\t2\nunknown\ndone\n").unwrap();
+    assert_eq!(
+        progress.read(),
+        vec![
+            ProgressEvent::Phase { phase: Phase::Building, steps: Some((1, 2)) },
+            ProgressEvent::Done,
+        ]
+    );
+    let path = progress.path().to_path_buf();
+    drop(progress);
+    assert!(!path.exists());
+}
@@ -0,0 +1,145 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+use snowflakeos_module_manager::modules::{
+    load::loadmodules,
+    roots::{moduleroots, ModuleRoot, ModuleRootKind, MODULES_PATH_ENV, SYSTEM_MODULES_DIR},
+};
+
+/// Tests change the environment `moduleroots` reads, so they run one at a time.
+static ENV: Mutex<()> = Mutex::new(());
+
+fn setenv(modulespath: Option<&str>, datahome: Option<&str>, datadirs: Option<&str>) {
+    for (name, value) in [
+        (MODULES_PATH_ENV, modulespath),
+        ("XDG_DATA_HOME", datahome),
+        ("XDG_DATA_DIRS", datadirs),
+    ] {
+        match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name),
+        }
+    }
+}
+
+fn tempdir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("smm-roots-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn addmodule(root: &Path, id: &str, name: &str) {
+    let dir = root.join(id);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("module.yml"),
+        format!("name: {}\nid: {}\nflake: test\nversion: '1.0'\noptions: []\n", name, id),
+    )
+    .unwrap();
+}
+
+#[test]
+fn roots_are_ordered_by_precedence() {
+    let _env = ENV.lock().unwrap_or_else(|x| x.into_inner());
+    setenv(Some("/env/a:/env/b"), Some("/home/user/.local/share"), Some("/usr/local/share:/usr/share"));
+    assert_eq!(
+        moduleroots(&[PathBuf::from("/cli")]),
+        vec![
+            ModuleRoot::new("/cli", ModuleRootKind::Override),
+            ModuleRoot::new("/env/a", ModuleRootKind::Override),
+            ModuleRoot::new("/env/b", ModuleRootKind::Override),
+            ModuleRoot::new("/home/user/.local/share/snowflakeos-modules", ModuleRootKind::User),
+            ModuleRoot::new("/usr/local/share/snowflakeos-modules", ModuleRootKind::Data),
+            ModuleRoot::new("/usr/share/snowflakeos-modules", ModuleRootKind::Data),
+            ModuleRoot::new(SYSTEM_MODULES_DIR, ModuleRootKind::System),
+        ]
+    );
+}
+
+#[test]
+fn duplicate_roots_keep_their_highest_precedence() {
+    let _env = ENV.lock().unwrap_or_else(|x| x.into_inner());
+    setenv(Some("/cli::/etc/snowflakeos-modules"), Some("/data"), Some("/data:/usr/share"));
+    assert_eq!(
+        moduleroots(&[PathBuf::from("/cli")]),
+        vec![
+            ModuleRoot::new("/cli", ModuleRootKind::Override),
+            ModuleRoot::new(SYSTEM_MODULES_DIR, ModuleRootKind::Override),
+            ModuleRoot::new("/data/snowflakeos-modules", ModuleRootKind::User),
+            ModuleRoot::new("/usr/share/snowflakeos-modules", ModuleRootKind::Data),
+        ]
+    );
+}
+
+#[test]
+fn empty_variables_use_the_defaults() {
+    let _env = ENV.lock().unwrap_or_else(|x| x.into_inner());
+    setenv(Some(""), Some(""), Some(""));
+    env::set_var("HOME", "/home/user");
+    assert_eq!(
+        moduleroots(&[]),
+        vec![
+            ModuleRoot::new("/home/user/.local/share/snowflakeos-modules", ModuleRootKind::User),
+            ModuleRoot::new("/usr/local/share/snowflakeos-modules", ModuleRootKind::Data),
+            ModuleRoot::new("/usr/share/snowflakeos-modules", ModuleRootKind::Data),
+            ModuleRoot::new(SYSTEM_MODULES_DIR, ModuleRootKind::System),
+        ]
+    );
+}
+
+#[test]
+fn modules_load_from_the_first_existing_root_with_their_id() {
+    let dir = tempdir("load");
+    let flakepath = dir.join("flake.nix");
+    fs::write(
+        &flakepath,
+        "{\n  outputs = {\n    systems.modules.nixos = [ test.nixosModules.shared ];\n  };\n}\n",
+    )
+    .unwrap();
+    let (first, second) = (dir.join("first"), dir.join("second"));
+    addmodule(&first, "shared", "First");
+    addmodule(&second, "shared", "Second");
+    addmodule(&second, "extra", "Extra");
+    let roots = [
+        ModuleRoot::new(dir.join("missing"), ModuleRootKind::Override),
+        ModuleRoot::new(&first, ModuleRootKind::User),
+        ModuleRoot::new(&second, ModuleRootKind::System),
+    ];
+    let loaded = loadmodules(&flakepath, &roots).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(loaded.diagnostics.is_empty());
+    assert_eq!(loaded.modules.len(), 1);
+    assert_eq!(loaded.modules[0].config.name, "First");
+    assert_eq!(loaded.modules[0].root, roots[1]);
+    assert_eq!(loaded.available.len(), 1);
+    assert_eq!(loaded.available[0].config.name, "Extra");
+    assert_eq!(loaded.available[0].root, roots[2]);
+}
+
+#[test]
+fn broken_modules_shadow_lower_precedence_roots() {
+    let dir = tempdir("broken");
+    let flakepath = dir.join("flake.nix");
+    fs::write(&flakepath, "{\n  outputs = {\n    systems.modules.nixos = [ ];\n  };\n}\n").unwrap();
+    let (first, second) = (dir.join("first"), dir.join("second"));
+    fs::create_dir_all(first.join("shared")).unwrap();
+    fs::write(first.join("shared").join("module.yml"), "name: [\n").unwrap();
+    addmodule(&second, "shared", "Second");
+    let roots = [
+        ModuleRoot::new(&first, ModuleRootKind::Override),
+        ModuleRoot::new(&second, ModuleRootKind::System),
+    ];
+    let loaded = loadmodules(&flakepath, &roots).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(loaded.modules.is_empty());
+    assert!(loaded.available.is_empty());
+    assert_eq!(loaded.diagnostics.len(), 1);
+    assert_eq!(loaded.diagnostics[0].file, first.join("shared").join("module.yml"));
+}
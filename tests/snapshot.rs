@@ -0,0 +1,101 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use snowflakeos_module_manager::modules::{
+    load::LoadDiagnostic,
+    roots::{ModuleRoot, ModuleRootKind},
+    snapshot::{pendingmodifications, Snapshot, SNAPSHOT_SCHEMA_VERSION},
+    MapType, MapValue, Module, ModuleData, ModuleOption, OptionData, OptionType,
+};
+
+/// Snapshot the fixture in `tests/fixtures/snapshot.json` was written from.
+fn snapshot() -> Snapshot {
+    let module = |name: &str, options: Vec<OptionData>| Module {
+        name: name.to_string(),
+        path: PathBuf::from("/etc/snowflakeos-modules").join(name),
+        root: ModuleRoot::new("/etc/snowflakeos-modules", ModuleRootKind::System),
+        flakeattr: format!("snowflakeos-modules.nixosModules.{}", name),
+        config: ModuleData {
+            name: name.to_uppercase(),
+            id: name.to_string(),
+            flake: String::from("snowflakeos-modules"),
+            description: Some(format!("The {} module", name)),
+            version: String::from("1.0.0"),
+            options,
+            icon: None,
+        },
+    };
+    let option = |id: &str, op_type: OptionType| OptionData {
+        label: id.to_string(),
+        id: id.to_string(),
+        description: None,
+        op_type,
+    };
+    let modules = [module(
+        "gnome",
+        vec![
+            option("modules.gnome.enable", OptionType::Switch { default: false }),
+            option(
+                "modules.gnome.session",
+                OptionType::Enum {
+                    default: String::from("\"gnome\""),
+                    options: HashMap::from([(String::from("\"gnome\""), String::from("GNOME"))]),
+                },
+            ),
+            option(
+                "modules.gnome.variables",
+                OptionType::Map {
+                    key_type: MapType::String,
+                    value_type: MapType::String,
+                    default: BTreeMap::new(),
+                },
+            ),
+        ],
+    )];
+    let available = [module("steam", vec![option("modules.steam.enable", OptionType::Switch { default: false })])];
+    let current = HashMap::from([(String::from("modules.gnome.enable"), ModuleOption::Switch { value: true })]);
+    let modified = HashMap::from([
+        (String::from("modules.gnome.enable"), ModuleOption::Switch { value: false }),
+        (
+            String::from("modules.gnome.variables"),
+            ModuleOption::Map {
+                value: BTreeMap::from([(String::from("EDITOR"), MapValue::String(String::from("vim")))]),
+            },
+        ),
+    ]);
+    Snapshot::new(
+        &modules,
+        &available,
+        &current,
+        pendingmodifications(&current, &modified),
+        &[LoadDiagnostic {
+            file: PathBuf::from("/etc/snowflakeos-modules/broken/module.yml"),
+            line: Some(3),
+            column: Some(7),
+            message: String::from("invalid type"),
+        }],
+    )
+}
+
+#[test]
+fn snapshot_json_matches_the_fixture() {
+    let fixture: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/snapshot.json")).unwrap();
+    assert_eq!(serde_json::to_value(snapshot()).unwrap(), fixture);
+}
+
+#[test]
+fn fixture_is_read_back_as_the_same_snapshot() {
+    let fixture: Snapshot = serde_json::from_str(include_str!("fixtures/snapshot.json")).unwrap();
+    assert_eq!(fixture, snapshot());
+}
+
+#[test]
+fn fixture_has_the_current_schema_version() {
+    // A fixture that no longer matches needs a new SNAPSHOT_SCHEMA_VERSION unless fields were only added
+    let fixture: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/snapshot.json")).unwrap();
+    assert_eq!(fixture["schema_version"], SNAPSHOT_SCHEMA_VERSION);
+}